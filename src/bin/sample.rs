@@ -1,7 +1,8 @@
 use std::io;
+use std::time::Duration;
 
 use rotor::os;
-use rotor::property::{apt, conf_file, dir, file, git, pacman};
+use rotor::property::{apk, apt, aur, conf_file, dir, dnf, file, git, pacman};
 use rotor::{prop, user, RotorBuilder};
 
 fn main() -> io::Result<()> {
@@ -28,13 +29,31 @@ fn main() -> io::Result<()> {
         )
         .host("192.168.1.1",
               user("root", prop::<os::DebianLike>()
+                  // update package lists at most once a day, then upgrade everything
+                  + apt::cache_fresh(Duration::from_secs(24 * 60 * 60))
+                  + apt::upgraded()
+                  // answer questions asked when tzdata is installed
+                  + apt::debconf("tzdata", "tzdata/Areas", "select", "Etc")
+                  + apt::debconf("tzdata", "tzdata/Zones/Etc", "select", "UTC")
                   + apt::installed("vim")
+                  + apt::installed("tzdata")
                   + file(".tmux.conf").contains_line("set -s escape-time 0")
               ).user("user", prop::<os::DebianLike>()
                   // make sure .bashrc contains a line configuring an alias
                   + file(".bashrc").contains_line("alias l='ls -CF'")
               )
         )
+        .host("fedora",
+              user("root", prop::<os::RedHatLike>()
+                  + dnf::installed_all(&["vim-enhanced", "tmux"])
+                  + dnf::removed("nano")
+              )
+        )
+        .host("container",
+              user("root", prop::<os::Alpine>()
+                  + apk::installed_all(&["bash", "git"])
+              )
+        )
         .host(
             "localhost",
             user(
@@ -43,6 +62,10 @@ fn main() -> io::Result<()> {
                     // add or set "enabled=False" in user-dirs.conf
                     // disable xdg user dirs such as "Music", "Pictures", "Public" in home directory
                     + conf_file::classic_syntax(".config/user-dirs.conf").value_set(("enabled", "False"))
+                    // remove the disabled xdg user dirs with everything in them
+                    + dir::path("Music").absent()
+                    + dir::path("Pictures").absent()
+                    + dir::path("Public").absent()
                     // add multiple lines to a file, setting aliases for fish
                     + file(".config/fish/conf.d/aliases.fish").contains_lines(&["alias l='exa'",
                     "alias v='nvim'"])
@@ -50,7 +73,14 @@ fn main() -> io::Result<()> {
                     // this is a simple script
                     + file("bin/em").content_bytes(b"#!/bin/sh\nemacsclient -c --alternate-editor \"\"")
             ).user("root", prop::<os::ArchLinux>()
+                // enable the multilib repository and colored output in pacman.conf
+                + pacman::conf().repo_enabled("multilib")
+                + pacman::conf().flag("Color")
                 + pacman::installed("bash")
+                // keep the kernel at a known good version until upgraded by hand
+                + pacman::installed("linux").version(">=6.1").held()
+                // build a package from the AUR as an unprivileged user
+                + aur::installed("tdrop").build_as("user")
             )
         )
         .run();
@@ -1,28 +1,52 @@
 use std::io;
 
 use super::types::os::OS;
-use super::PrResult;
+use super::util::passwd::Passwd;
+use super::util::privileges;
+use super::{Facts, PrResult};
 use crate::property::PropertyList;
 
-/// Something to run
+/// Something to run for the user of an account
 pub(super) trait Runnable {
-    fn run(&self) -> PrResult<()>;
+    /// Properties of the user run as the given account, when it's not
+    /// the one running rotor
+    fn run(&self, facts: &Facts, account: Option<&Passwd>) -> PrResult<()>;
+}
+
+/// Run with the privileges of the account if given
+fn run_as<F>(account: Option<&Passwd>, f: F) -> PrResult<bool>
+where
+    F: FnOnce() -> PrResult<bool>,
+{
+    match account {
+        Some(account) => privileges::as_user(account, f),
+        None => f(),
+    }
 }
 
 impl<T: OS> Runnable for PropertyList<T> {
-    fn run(&self) -> PrResult<()> {
+    fn run(&self, facts: &Facts, account: Option<&Passwd>) -> PrResult<()> {
         let total = self.properties.len();
         println!("Applying {} properties", total);
+        let account_facts = account.map(|a| facts.for_account(a));
         let mut failed = 0;
         for (property, i) in self.properties.iter().zip(1..) {
-            match property.check() {
+            // Files of another user are changed as that user, who is
+            // then the user in the facts
+            let (owner, facts) = match (account, &account_facts) {
+                (Some(account), Some(account_facts)) if property.user_owned() => {
+                    (Some(account), account_facts)
+                }
+                _ => (None, facts),
+            };
+            match run_as(owner, || property.check(facts)) {
                 Ok(true) => {
                     println!("[{}/{}] {}: YES!", i, total, property);
                 }
                 Ok(false) => {
                     println!("[{}/{}] {}: applying", i, total, property);
-                    match property.apply() {
-                        Ok(()) => println!("[{}/{}] applied.", i, total),
+                    match run_as(owner, || property.apply(facts).map(|()| true)) {
+                        Ok(_) => println!("[{}/{}] applied.", i, total),
                         Err(e) => {
                             eprintln!(
                                 "[{}/{}] failed to apply {} because of {}.",
@@ -0,0 +1,172 @@
+//! Facts about the machine, gathered once per run and passed to
+//! properties so they don't each have to look them up
+
+use crate::types::os::OsRelease;
+use crate::util::passwd::Passwd;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+
+/// What properties may want to know about the host and the user
+/// running rotor
+#[derive(Clone, Debug, Default)]
+pub struct Facts {
+    pub hostname: String,
+    pub os_release: OsRelease,
+    /// Machine hardware name, such as `x86_64` or `aarch64`
+    pub arch: String,
+    pub cpus: usize,
+    /// Total memory in bytes
+    pub memory: u64,
+    pub user: String,
+    pub uid: u32,
+    pub home: PathBuf,
+    /// Names of executables in the directories of PATH
+    commands: HashSet<String>,
+}
+
+impl Facts {
+    pub fn gather() -> io::Result<Facts> {
+        let os_release = match OsRelease::read() {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => OsRelease::default(),
+            release => release?,
+        };
+        let uid = unsafe { libc::geteuid() };
+        let (user, home) = account(uid, Passwd::by_uid(uid)?);
+        Ok(Facts {
+            hostname: hostname()?,
+            os_release,
+            arch: arch(),
+            cpus: thread::available_parallelism().map_or(1, |n| n.get()),
+            memory: memory()?,
+            user,
+            uid,
+            home,
+            commands: commands(),
+        })
+    }
+
+    /// Gather what can be gathered, leaving the rest unknown, for
+    /// when nothing has to be detected from the facts
+    pub fn gather_partial() -> Facts {
+        let uid = unsafe { libc::geteuid() };
+        let (user, home) = account(uid, Passwd::by_uid(uid).unwrap_or(None));
+        Facts {
+            hostname: hostname().unwrap_or_default(),
+            os_release: OsRelease::read().unwrap_or_default(),
+            arch: arch(),
+            cpus: thread::available_parallelism().map_or(1, |n| n.get()),
+            memory: memory().unwrap_or(0),
+            user,
+            uid,
+            home,
+            commands: commands(),
+        }
+    }
+
+    /// The same host as seen by another user
+    pub fn for_account(&self, account: &Passwd) -> Facts {
+        Facts {
+            user: account.name.clone(),
+            uid: account.uid,
+            home: account.home.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether a command can be run without its full path
+    pub fn has_command(&self, name: &str) -> bool {
+        self.commands.contains(name)
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+}
+
+/// The name and home of a uid
+fn account(uid: u32, passwd: Option<Passwd>) -> (String, PathBuf) {
+    let user = match &passwd {
+        Some(p) => p.name.clone(),
+        None => env::var("USER").unwrap_or_else(|_| uid.to_string()),
+    };
+    // HOME may belong to another user, such as under sudo
+    let home = match (passwd, dirs::home_dir()) {
+        (Some(p), _) => p.home,
+        (None, Some(home)) => home,
+        (None, None) => PathBuf::from("/"),
+    };
+    (user, home)
+}
+
+/// The kernel's hostname, falling back to /etc/hostname
+fn hostname() -> io::Result<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))?;
+    Ok(name.trim().to_string())
+}
+
+fn arch() -> String {
+    let uname = process::Command::new("uname")
+        .arg("-m")
+        .stderr(process::Stdio::null())
+        .output();
+    match uname {
+        Ok(ref out) if out.status.success() => {
+            String::from_utf8_lossy(&out.stdout).trim().to_string()
+        }
+        _ => env::consts::ARCH.to_string(),
+    }
+}
+
+/// MemTotal of /proc/meminfo, such as `MemTotal: 16318412 kB`
+fn memory() -> io::Result<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    Ok(parse_mem_total(&meminfo).unwrap_or(0))
+}
+
+fn parse_mem_total(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|l| l.starts_with("MemTotal:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+fn commands() -> HashSet<String> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    let mut commands = HashSet::new();
+    for dir in env::split_paths(&path) {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let executable = fs::metadata(entry.path())
+                .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false);
+            if executable {
+                commands.insert(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_facts() {
+        assert_eq!(
+            parse_mem_total("MemTotal:       16318412 kB\nMemFree: 1 kB\n"),
+            Some(16318412 * 1024)
+        );
+        assert_eq!(parse_mem_total("MemFree: 1 kB\n"), None);
+        assert_eq!(parse_mem_total("MemTotal: many kB\n"), None);
+    }
+}
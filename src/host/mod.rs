@@ -1,17 +1,42 @@
-use crate::types::os::OS;
+use crate::types::os::{OsRelease, OS};
 use std::io;
 
+mod facts;
 mod user;
+pub use self::facts::Facts;
 pub use self::user::user;
 pub use self::user::HostUsersConf;
+use self::user::UserConf;
 use crate::effect::Runnable;
+use crate::util::passwd::Passwd;
+use crate::util::set_target_home;
 use crate::PrResult;
 use std::fmt;
 use std::str::FromStr;
 
 pub trait ConfigureUser {
     fn list_users(&self) -> Vec<&str>;
-    fn configure(&self, user_name: &str) -> PrResult<()>;
+    /// Refuses to run on a host running another OS unless forced
+    fn configure(&self, user_name: &str, facts: &Facts, force: bool) -> PrResult<()>;
+    /// Configure every user of the host, which needs root unless
+    /// there's only the current user
+    fn configure_all(&self, facts: &Facts, force: bool) -> PrResult<()>;
+}
+
+/// Check that the running OS is the one properties are declared for
+fn verify_os<O: OS>(release: &OsRelease) -> PrResult<()> {
+    if O::matches(release) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "Host runs {} but is configured for {}, use --force to apply anyway",
+                release,
+                O::NAME
+            ),
+        ))
+    }
 }
 
 impl<O: OS> ConfigureUser for HostUsersConf<O> {
@@ -19,19 +44,76 @@ impl<O: OS> ConfigureUser for HostUsersConf<O> {
         self.users.iter().map(|u| u.name.as_ref()).collect()
     }
 
-    fn configure(&self, user_name: &str) -> PrResult<()> {
+    fn configure(&self, user_name: &str, facts: &Facts, force: bool) -> PrResult<()> {
+        if !force {
+            verify_os::<O>(&facts.os_release)?;
+        }
+        let user = self
+            .users
+            .iter()
+            .find(|u| u.name == user_name)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("User {} not configured", user_name),
+                )
+            })?;
+        configure_account(user, facts)
+    }
+
+    fn configure_all(&self, facts: &Facts, force: bool) -> PrResult<()> {
+        if !force {
+            verify_os::<O>(&facts.os_release)?;
+        }
+        let mut failed = vec![];
         for user in &self.users {
-            if user.name == user_name {
-                return user.properties.run();
+            println!("Configuring user {}", user.name);
+            if let Err(e) = configure_account(user, facts) {
+                eprintln!("User {} not configured correctly: {}", user.name, e);
+                failed.push(user.name.as_str());
             }
         }
-        Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("User {} not configured", user_name),
-        ))
+        if !failed.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Users {:?} not configured correctly", failed),
+            ));
+        }
+        Ok(())
     }
 }
 
+/// Apply properties of a user with an account on this host. Paths in
+/// the home directory are resolved in the home of that account.
+fn configure_account<O: OS>(user: &UserConf<O>, facts: &Facts) -> PrResult<()> {
+    let account = Passwd::by_name(&user.name)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("User {} has no account on this host", user.name),
+        )
+    })?;
+    configure_as(user, &account, facts)
+}
+
+fn configure_as<O: OS>(user: &UserConf<O>, account: &Passwd, facts: &Facts) -> PrResult<()> {
+    // Already running as the user, possibly with the HOME of another
+    let as_account = if account.uid == facts.uid {
+        None
+    } else if facts.is_root() {
+        Some(account)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Configuring {} as {} needs root", user.name, facts.user),
+        ));
+    };
+    set_target_home(Some(account.home.clone()));
+    let result = user.properties.run(facts, as_account);
+    set_target_home(None);
+    result
+}
+
+#[derive(Clone)]
 pub struct UserAtHost {
     pub user: String,
     pub host: String,
@@ -68,3 +150,77 @@ impl FromStr for UserAtHost {
         Ok(UserAtHost { user, host })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os;
+    use crate::property::{file, prop};
+    use crate::util::home_dir;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_configure_as() {
+        let mut facts = Facts::default();
+        facts.user = "root".to_string();
+        facts.home = PathBuf::from("/root");
+        let home = temp_dir().join("rotor-configure-as-test");
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(&home).unwrap();
+        let account = Passwd {
+            name: "rotor-test".to_string(),
+            uid: 65534,
+            gid: 65534,
+            home: home.clone(),
+            shell: "/bin/sh".to_string(),
+        };
+        let conf = UserConf::new(
+            account.name.clone(),
+            prop::<os::Any>() + file(".profile").contains_line("umask 022"),
+        );
+        // The same user still writes to the home of the account
+        let same = Passwd {
+            uid: unsafe { libc::geteuid() },
+            ..account.clone()
+        };
+        facts.uid = same.uid;
+        configure_as(&conf, &same, &facts).unwrap();
+        assert!(fs::read_to_string(home.join(".profile"))
+            .unwrap()
+            .contains("umask 022\n"));
+        assert_eq!(home_dir(), dirs::home_dir());
+        fs::remove_file(home.join(".profile")).unwrap();
+
+        if unsafe { libc::geteuid() } != 0 {
+            facts.uid = 1000;
+            assert!(configure_as(&conf, &account, &facts).is_err());
+            return;
+        }
+        let path = home.join(".profile");
+        // The account can't write to a home owned by root
+        assert!(configure_as(&conf, &account, &facts).is_err());
+        assert!(!path.exists());
+
+        let dir = std::ffi::CString::new(home.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::chown(dir.as_ptr(), 65534, 65534) }, 0);
+        configure_as(&conf, &account, &facts).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("umask 022\n"));
+        assert_eq!(fs::metadata(&path).unwrap().uid(), 65534);
+        assert_eq!(home_dir(), dirs::home_dir());
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_configure_all() {
+        let conf = user("rotor-no-such-user", prop::<os::Any>())
+            .user("rotor-no-such-user-either", prop::<os::Any>());
+        let e = conf.configure_all(&Facts::default(), true).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Users [\"rotor-no-such-user\", \"rotor-no-such-user-either\"] not configured correctly"
+        );
+    }
+}
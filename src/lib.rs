@@ -1,3 +1,5 @@
+#![allow(clippy::io_other_error)]
+
 mod effect;
 mod host;
 pub mod property;
@@ -9,8 +11,11 @@ pub use self::types::os;
 
 pub use self::host::user;
 use self::host::ConfigureUser;
+pub use self::host::Facts;
 use self::host::HostUsersConf;
-use self::util::cmd::{RotorMain, RotorSub};
+use self::host::UserAtHost;
+use self::util::cmd::{RotorMain, RotorSub, Target};
+use self::util::near_matches;
 use std::collections::HashMap;
 use std::io;
 use structopt::StructOpt;
@@ -37,12 +42,48 @@ impl RotorBuilder {
         self
     }
 
-    pub fn configure_user(&self, username: &str, hostname: &str) -> PrResult<()> {
-        let host = self
-            .hosts
-            .get(hostname)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host not configured"))?;
-        host.configure(username)
+    /// Apply properties of a user at a host. Unless forced, the host
+    /// must run the OS the properties are declared for.
+    pub fn configure_user(&self, username: &str, hostname: &str, force: bool) -> PrResult<()> {
+        let facts = Facts::gather()?;
+        self.configure(username, hostname, &facts, force)
+    }
+
+    /// Apply properties of every user at a host, which needs root
+    /// to configure other users
+    pub fn configure_host(&self, hostname: &str, force: bool) -> PrResult<()> {
+        let facts = Facts::gather()?;
+        self.find_host(hostname)?.configure_all(&facts, force)
+    }
+
+    fn find_host(&self, hostname: &str) -> PrResult<&dyn ConfigureUser> {
+        match self.hosts.get(hostname) {
+            Some(host) => Ok(host.as_ref()),
+            None => {
+                let hosts = self.hosts.keys().map(|h| h.as_str());
+                Err(not_configured(
+                    "Host",
+                    hostname,
+                    near_matches(hostname, hosts),
+                ))
+            }
+        }
+    }
+
+    fn configure(
+        &self,
+        username: &str,
+        hostname: &str,
+        facts: &Facts,
+        force: bool,
+    ) -> PrResult<()> {
+        let host = self.find_host(hostname)?;
+        let users = host.list_users();
+        if !users.contains(&username) {
+            let similar = near_matches(username, users.iter().cloned());
+            return Err(not_configured("User", username, similar));
+        }
+        host.configure(username, facts, force)
     }
 
     /// Parse command-line arguments and run
@@ -58,10 +99,35 @@ impl RotorBuilder {
                     }
                 }
             }
-            RotorSub::Apply { ref user } => {
-                println!("Configuring as {}", user);
-                if let Err(e) = self.configure_user(&user.user, &user.host) {
-                    eprintln!("{} not configured correctly: {:?}", user, e);
+            RotorSub::Apply { ref target, force } => {
+                // Facts are only required to find out who to configure
+                let facts = match (target, Facts::gather()) {
+                    (_, Ok(facts)) => facts,
+                    (Some(_), Err(_)) => Facts::gather_partial(),
+                    (None, Err(e)) => {
+                        eprintln!("Failed to gather facts about this host: {}", e);
+                        return;
+                    }
+                };
+                // Without a target, configure whoever is running us here
+                let current = Target::User(UserAtHost {
+                    user: facts.user.clone(),
+                    host: facts.hostname.clone(),
+                });
+                let target = target.as_ref().unwrap_or(&current);
+                let result = match target {
+                    Target::User(user) => {
+                        println!("Configuring as {}", user);
+                        self.configure(&user.user, &user.host, &facts, force)
+                    }
+                    Target::Host(host) => {
+                        println!("Configuring all users of {}", host);
+                        self.find_host(host)
+                            .and_then(|h| h.configure_all(&facts, force))
+                    }
+                };
+                if let Err(e) = result {
+                    eprintln!("{} not configured correctly: {}", target, e);
                 }
             }
             RotorSub::Push { ref targets } => {
@@ -72,6 +138,17 @@ impl RotorBuilder {
     }
 }
 
+/// A host or user is not in the configuration, suggesting names
+/// which might have been meant
+fn not_configured(kind: &str, name: &str, mut similar: Vec<&str>) -> io::Error {
+    let mut msg = format!("{} {} not configured", kind, name);
+    if !similar.is_empty() {
+        similar.sort();
+        msg.push_str(&format!(", did you mean {}?", similar.join(" or ")));
+    }
+    io::Error::new(io::ErrorKind::NotFound, msg)
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
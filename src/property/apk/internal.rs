@@ -0,0 +1,159 @@
+use crate::os;
+use crate::property::installed::InstalledCache;
+use crate::property::Property;
+use crate::Facts;
+use crate::PrResult;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::process;
+
+const INSTALLED_DB: &str = "/lib/apk/db/installed";
+
+/// Packages in the apk database, shared by all properties
+static INSTALLED: InstalledCache = InstalledCache::new();
+
+/// Names and versions of installed packages, and names provided by
+/// them such as `cmd:vim`, read from the apk database
+fn query_installed() -> io::Result<HashMap<String, String>> {
+    Ok(parse_installed(&fs::read_to_string(INSTALLED_DB)?))
+}
+
+/// Parse the database, where each package is a paragraph of fields
+/// such as `P:vim` for the name and `V:9.0.2073-r0` for the version
+fn parse_installed(content: &str) -> HashMap<String, String> {
+    let mut installed = HashMap::new();
+    let mut provided = vec![];
+    for paragraph in content.split("\n\n") {
+        let mut name = None;
+        let mut version = None;
+        for line in paragraph.lines() {
+            match line.split_at(line.find(':').map_or(0, |i| i + 1)) {
+                ("P:", value) => name = Some(value),
+                ("V:", value) => version = Some(value),
+                // Such as "cmd:vim=9.0.2073-r0 so:libc.musl-x86_64.so.1=1"
+                ("p:", value) => provided.extend(value.split_whitespace().map(|p| {
+                    let mut parts = p.splitn(2, '=');
+                    let name = parts.next().unwrap_or(p).to_string();
+                    (name, parts.next().unwrap_or("").to_string())
+                })),
+                _ => {}
+            }
+        }
+        if let Some(name) = name {
+            installed.insert(name.to_string(), version.unwrap_or("").to_string());
+        }
+    }
+    for (name, version) in provided {
+        installed.entry(name).or_insert(version);
+    }
+    installed
+}
+
+fn run_apk(args: &[&str], packages: &[&str]) -> io::Result<()> {
+    let s = process::Command::new("apk")
+        .args(args)
+        .args(packages)
+        .status()?;
+    INSTALLED.invalidate();
+    if !s.success() {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("apk {} {:?} failed", args.join(" "), packages),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// packages are installed by apk
+#[derive(Clone)]
+pub struct ApkInstalled {
+    pub packages: Vec<&'static str>,
+}
+
+/// packages are not installed
+#[derive(Clone)]
+pub struct ApkRemoved {
+    pub packages: Vec<&'static str>,
+}
+
+impl fmt::Display for ApkInstalled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.packages.len() == 1 {
+            write!(f, "package {} is installed by apk", self.packages[0])
+        } else {
+            write!(f, "packages {:?} are installed by apk", self.packages)
+        }
+    }
+}
+
+impl fmt::Display for ApkRemoved {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.packages.len() == 1 {
+            write!(f, "package {} is not installed", self.packages[0])
+        } else {
+            write!(f, "packages {:?} are not installed", self.packages)
+        }
+    }
+}
+
+impl Property<os::Alpine> for ApkInstalled {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let missing = INSTALLED.missing(&self.packages, query_installed)?;
+        Ok(missing.is_empty())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let missing = INSTALLED.missing(&self.packages, query_installed)?;
+        run_apk(&["add", "--no-cache"], &missing)
+    }
+}
+
+impl Property<os::Alpine> for ApkRemoved {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let missing = INSTALLED.missing(&self.packages, query_installed)?;
+        Ok(missing.len() == self.packages.len())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let missing = INSTALLED.missing(&self.packages, query_installed)?;
+        let installed: Vec<&str> = self
+            .packages
+            .iter()
+            .filter(|p| !missing.contains(p))
+            .cloned()
+            .collect();
+        run_apk(&["del"], &installed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+C:Q1abc=
+P:musl
+V:1.2.4-r2
+A:x86_64
+p:so:libc.musl-x86_64.so.1=1
+
+C:Q1def=
+P:vim
+V:9.0.2073-r0
+A:x86_64
+p:cmd:vim=9.0.2073-r0 cmd:vimdiff=9.0.2073-r0
+";
+
+    #[test]
+    fn test_parse_installed() {
+        let installed = parse_installed(SAMPLE);
+        assert_eq!(installed["musl"], "1.2.4-r2");
+        assert_eq!(installed["vim"], "9.0.2073-r0");
+        assert!(installed.contains_key("cmd:vimdiff"));
+        assert!(installed.contains_key("so:libc.musl-x86_64.so.1"));
+        assert!(!installed.contains_key("nano"));
+    }
+}
@@ -0,0 +1,32 @@
+//! Manage packages on Alpine Linux
+//! apk may be invoked
+
+mod internal;
+
+use self::internal::{ApkInstalled, ApkRemoved};
+
+/// package is installed by apk
+#[allow(dead_code)]
+pub fn installed(package: &'static str) -> ApkInstalled {
+    installed_all(&[package])
+}
+
+/// packages are installed by apk
+#[allow(dead_code)]
+pub fn installed_all(packages: &[&'static str]) -> ApkInstalled {
+    let packages = packages.to_vec();
+    ApkInstalled { packages }
+}
+
+/// package is not installed
+#[allow(dead_code)]
+pub fn removed(package: &'static str) -> ApkRemoved {
+    removed_all(&[package])
+}
+
+/// packages are not installed
+#[allow(dead_code)]
+pub fn removed_all(packages: &[&'static str]) -> ApkRemoved {
+    let packages = packages.to_vec();
+    ApkRemoved { packages }
+}
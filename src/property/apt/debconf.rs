@@ -0,0 +1,152 @@
+//! Answers to debconf questions, set before packages are installed so
+//! they don't prompt or take defaults
+
+use super::internal::is_installed;
+use crate::os;
+use crate::property::Property;
+use crate::Facts;
+use crate::PrResult;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::process;
+
+/// Where debconf keeps answers, including those preseeded for
+/// packages which aren't installed yet
+const CONFIG: &str = "/var/cache/debconf/config.dat";
+
+/// Where debconf keeps password answers, readable only by root
+const PASSWORDS: &str = "/var/cache/debconf/passwords.dat";
+
+/// A debconf question of a package has the given answer
+#[derive(Clone)]
+pub struct AptDebconf {
+    pub package: &'static str,
+    pub question: &'static str,
+    /// Such as `string`, `boolean`, `select` or `password`
+    pub kind: &'static str,
+    pub value: &'static str,
+}
+
+/// Parse a debconf database, which has a stanza per question with
+/// `Name:` and `Value:` fields
+fn parse_database(content: &str) -> HashMap<String, String> {
+    content
+        .split("\n\n")
+        .filter_map(|stanza| {
+            let field = |name: &str| {
+                stanza
+                    .lines()
+                    .find_map(|l| l.strip_prefix(name)?.strip_prefix(':'))
+                    .map(|v| v.trim().to_string())
+            };
+            Some((field("Name")?, field("Value").unwrap_or_default()))
+        })
+        .collect()
+}
+
+/// Current answers in a debconf database
+fn read_database(path: &str) -> io::Result<HashMap<String, String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(parse_database(&content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+impl fmt::Display for AptDebconf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.kind == "password" {
+            write!(f, "debconf {} of {} is set", self.question, self.package)
+        } else {
+            write!(
+                f,
+                "debconf {} of {} is {}",
+                self.question, self.package, self.value
+            )
+        }
+    }
+}
+
+impl Property<os::DebianLike> for AptDebconf {
+    /// Answers are read from the databases, as `debconf-show` only
+    /// lists questions of installed packages and hides passwords
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let database = if self.kind == "password" {
+            PASSWORDS
+        } else {
+            CONFIG
+        };
+        let answers = read_database(database)?;
+        Ok(answers.get(self.question).map(|v| v.as_str()) == Some(self.value))
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let mut child = process::Command::new("debconf-set-selections")
+            .stdin(process::Stdio::piped())
+            .spawn()?;
+        let selection = format!(
+            "{} {} {} {}\n",
+            self.package, self.question, self.kind, self.value
+        );
+        child
+            .stdin
+            .take()
+            .expect("debconf-set-selections has piped stdin")
+            .write_all(selection.as_bytes())?;
+        if !child.wait()?.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("debconf-set-selections failed for {}", self.question),
+            ));
+        }
+        // Installed packages only read answers when reconfigured
+        if is_installed(self.package)? {
+            let s = process::Command::new("dpkg-reconfigure")
+                .env("DEBIAN_FRONTEND", "noninteractive")
+                .arg(self.package)
+                .status()?;
+            if !s.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("dpkg-reconfigure {} failed", self.package),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_database() {
+        let content = "\
+Name: tzdata/Areas
+Template: tzdata/Areas
+Value: Europe
+Owners: tzdata
+Flags: seen
+Variables:
+ Value = ignored
+
+Name: mysql-server/root_password
+Template: mysql-server/root_password
+Value: secret
+Owners: mysql-server
+Flags: seen
+
+Name: mysql-server/root_password_again
+Template: mysql-server/root_password_again
+Owners: mysql-server
+";
+        let answers = parse_database(content);
+        assert_eq!(answers["tzdata/Areas"], "Europe");
+        assert_eq!(answers["mysql-server/root_password"], "secret");
+        assert_eq!(answers["mysql-server/root_password_again"], "");
+    }
+}
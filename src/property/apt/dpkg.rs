@@ -0,0 +1,226 @@
+//! States of packages read from the dpkg database
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+const STATUS_FILE: &str = "/var/lib/dpkg/status";
+
+/// What dpkg knows about a package
+#[derive(Clone, Debug, PartialEq)]
+pub enum PackageState {
+    /// Installed and configured, or provided by an installed package
+    Installed,
+    /// Known to dpkg but not installed, or only its configuration
+    /// files are left
+    NotInstalled,
+    /// Not in the dpkg database and not provided by any package
+    Unknown,
+    /// Installation or removal didn't finish, such as `half-configured`
+    /// or `unpacked`
+    Broken(String),
+}
+
+impl fmt::Display for PackageState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackageState::Installed => write!(f, "installed"),
+            PackageState::NotInstalled => write!(f, "not installed"),
+            PackageState::Unknown => write!(f, "unknown to dpkg"),
+            PackageState::Broken(status) => write!(f, "{}", status),
+        }
+    }
+}
+
+/// A package in the dpkg database
+#[derive(Clone, Debug)]
+struct Entry {
+    state: PackageState,
+    /// Virtual packages have no version
+    version: Option<String>,
+    /// Selected to be held back from upgrades
+    held: bool,
+}
+
+/// Packages in the dpkg database
+#[derive(Debug, Default)]
+pub struct DpkgStatus {
+    /// Packages by name and by `name:arch`. Installed architectures
+    /// take precedence.
+    packages: HashMap<String, Entry>,
+}
+
+impl DpkgStatus {
+    pub fn read() -> io::Result<DpkgStatus> {
+        Ok(DpkgStatus::parse(&fs::read_to_string(STATUS_FILE)?))
+    }
+
+    pub fn parse(content: &str) -> DpkgStatus {
+        let mut status = DpkgStatus::default();
+        // Virtual packages provided by installed packages
+        let mut provided = vec![];
+        for paragraph in content.split("\n\n") {
+            let mut package = None;
+            let mut arch = None;
+            let mut state = None;
+            let mut version = None;
+            let mut held = false;
+            let mut provides = None;
+            for line in paragraph.lines() {
+                // Continuation lines start with whitespace
+                let mut field = line.splitn(2, ':');
+                let (name, value) = match (field.next(), field.next()) {
+                    (Some(n), Some(v)) if !n.starts_with(char::is_whitespace) => (n, v.trim()),
+                    _ => continue,
+                };
+                match name {
+                    "Package" => package = Some(value),
+                    "Architecture" => arch = Some(value),
+                    "Status" => {
+                        state = Some(parse_state(value));
+                        held = value.split_whitespace().next() == Some("hold");
+                    }
+                    "Version" => version = Some(value.to_string()),
+                    "Provides" => provides = Some(value),
+                    _ => {}
+                }
+            }
+            let (package, state) = match (package, state) {
+                (Some(p), Some(s)) => (p, s),
+                _ => continue,
+            };
+            if state == PackageState::Installed {
+                if let Some(provides) = provides {
+                    // Such as "mail-transport-agent, default-mta (= 4.96)"
+                    for p in provides.split(',') {
+                        if let Some(name) = p.split_whitespace().next() {
+                            provided.push(name.to_string());
+                        }
+                    }
+                }
+            }
+            let entry = Entry {
+                state,
+                version,
+                held,
+            };
+            if let Some(arch) = arch {
+                status.insert(format!("{}:{}", package, arch), entry.clone());
+            }
+            status.insert(package.to_string(), entry);
+        }
+        for name in provided {
+            let entry = Entry {
+                state: PackageState::Installed,
+                version: None,
+                held: false,
+            };
+            status.insert(name, entry);
+        }
+        status
+    }
+
+    fn insert(&mut self, name: String, entry: Entry) {
+        let installed = self
+            .packages
+            .get(&name)
+            .is_some_and(|e| e.state == PackageState::Installed);
+        if !installed {
+            self.packages.insert(name, entry);
+        }
+    }
+
+    /// State of a package, which may be qualified with an architecture
+    pub fn state(&self, package: &str) -> PackageState {
+        self.packages
+            .get(package)
+            .map_or(PackageState::Unknown, |e| e.state.clone())
+    }
+
+    /// Version of an installed package, unless it is only provided
+    /// by other packages
+    pub fn version(&self, package: &str) -> Option<&str> {
+        self.packages
+            .get(package)
+            .filter(|e| e.state == PackageState::Installed)
+            .and_then(|e| e.version.as_deref())
+    }
+
+    /// Whether a package is held back from upgrades
+    pub fn held(&self, package: &str) -> bool {
+        self.packages.get(package).is_some_and(|e| e.held)
+    }
+}
+
+/// Parse a status such as `install ok installed`
+fn parse_state(status: &str) -> PackageState {
+    match status.split_whitespace().nth(2) {
+        Some("installed") => PackageState::Installed,
+        Some("not-installed") | Some("config-files") => PackageState::NotInstalled,
+        Some(other) => PackageState::Broken(other.to_string()),
+        None => PackageState::Broken(status.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+Package: vim
+Status: install ok installed
+Architecture: amd64
+Version: 2:9.0.1378-2
+Description: Vi IMproved
+ Vim is an almost compatible version of the UNIX editor Vi.
+
+Package: exim4-daemon-light
+Status: install ok installed
+Architecture: amd64
+Provides: mail-transport-agent, default-mta (= 4.96)
+
+Package: libc6
+Status: hold ok installed
+Architecture: amd64
+Version: 2.36-9
+
+Package: libc6
+Status: deinstall ok config-files
+Architecture: i386
+
+Package: nano
+Status: deinstall ok config-files
+Architecture: amd64
+
+Package: postfix
+Status: install ok half-configured
+Architecture: amd64
+";
+
+    #[test]
+    fn test_dpkg_status() {
+        let status = DpkgStatus::parse(SAMPLE);
+        assert_eq!(status.state("vim"), PackageState::Installed);
+        assert_eq!(status.state("vim:amd64"), PackageState::Installed);
+        assert_eq!(
+            status.state("mail-transport-agent"),
+            PackageState::Installed
+        );
+        assert_eq!(status.state("libc6"), PackageState::Installed);
+        assert_eq!(status.state("libc6:i386"), PackageState::NotInstalled);
+        assert_eq!(status.state("nano"), PackageState::NotInstalled);
+        assert_eq!(status.state("emacs"), PackageState::Unknown);
+        assert_eq!(
+            status.state("postfix"),
+            PackageState::Broken("half-configured".to_string())
+        );
+        assert_eq!(status.version("vim"), Some("2:9.0.1378-2"));
+        assert_eq!(status.version("libc6:amd64"), Some("2.36-9"));
+        assert_eq!(status.version("mail-transport-agent"), None);
+        assert_eq!(status.version("nano"), None);
+        assert!(status.held("libc6"));
+        assert!(!status.held("libc6:i386"));
+        assert!(!status.held("vim"));
+    }
+}
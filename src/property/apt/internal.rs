@@ -1,14 +1,36 @@
+use super::dpkg::{DpkgStatus, PackageState};
+use super::update::update_if_stale;
 use crate::os;
+use crate::property::installed::InstalledCache;
+use crate::property::version::{dpkg_cmp, Op, VersionReq};
 use crate::property::Property;
+use crate::Facts;
 use crate::PrResult;
 use std::fmt;
 use std::io;
 use std::process;
 
-/// packages are installed by pacman
+/// Packages in the dpkg database, shared by all properties
+static INSTALLED: InstalledCache<DpkgStatus> = InstalledCache::new();
+
+/// Packages have been installed, upgraded or removed by other means
+pub(super) fn installed_changed() {
+    INSTALLED.invalidate();
+}
+
+/// Whether a package is installed, according to the dpkg database
+pub(super) fn is_installed(package: &str) -> io::Result<bool> {
+    INSTALLED.with(DpkgStatus::read, |status| {
+        status.state(package) == PackageState::Installed
+    })
+}
+
+/// packages are installed by apt
 #[derive(Clone)]
 pub struct AptInstalled {
     pub packages: Vec<&'static str>,
+    version: Option<VersionReq>,
+    held: bool,
 }
 
 #[derive(Clone)]
@@ -23,54 +45,188 @@ impl fmt::Display for AptInstalled {
         } else {
             write!(f, "packages {:?} are installed by apt", self.packages)?;
         }
+        if let Some(req) = &self.version {
+            write!(f, " with version {}", req)?;
+        }
+        if self.held {
+            write!(f, " and held")?;
+        }
 
         Ok(())
     }
 }
 
-impl Property<os::DebianLike> for AptInstalled {
-    fn check(&self) -> PrResult<bool> {
-        let ps = &self.packages;
-        let out = process::Command::new("apt-cache")
-            .env("LANG", "C")
-            .arg("policy")
-            .args(ps)
-            .output()?
-            .stdout;
-        let out =
-            String::from_utf8(out).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        let is: Vec<bool> = out
-            .lines()
-            .filter_map(|l| {
-                if l.contains("Installed: (none)") {
-                    Some(false)
-                } else if l.contains("Installed: ") {
-                    Some(true)
-                } else {
-                    None
+/// Why a package doesn't satisfy the property
+enum Unsatisfied {
+    State(PackageState),
+    Version(String),
+}
+
+/// Differences between the declared and installed packages
+struct AptReconciliation {
+    /// Not installed, or without the required version
+    unsatisfied: Vec<(&'static str, Unsatisfied)>,
+    /// Should be held but aren't
+    not_held: Vec<&'static str>,
+}
+
+impl AptInstalled {
+    pub fn new(packages: Vec<&'static str>) -> AptInstalled {
+        AptInstalled {
+            packages,
+            version: None,
+            held: false,
+        }
+    }
+
+    /// Installed versions satisfy a requirement, compared like dpkg.
+    /// An exact version such as `1.2-3` is installed with
+    /// `apt-get install package=1.2-3`, others are only checked.
+    #[allow(dead_code)]
+    pub fn version(mut self, req: &str) -> AptInstalled {
+        self.version = Some(VersionReq::parse(req));
+        self
+    }
+
+    /// Packages are held back from upgrades with `apt-mark hold`
+    #[allow(dead_code)]
+    pub fn held(mut self) -> AptInstalled {
+        self.held = true;
+        self
+    }
+
+    fn reconcile(&self) -> io::Result<AptReconciliation> {
+        INSTALLED.with(DpkgStatus::read, |status| {
+            let mut unsatisfied = vec![];
+            let mut not_held = vec![];
+            for &p in &self.packages {
+                let state = status.state(p);
+                if state != PackageState::Installed {
+                    unsatisfied.push((p, Unsatisfied::State(state)));
+                } else if let (Some(req), Some(v)) = (&self.version, status.version(p)) {
+                    if !req.matches(v, dpkg_cmp) {
+                        unsatisfied.push((p, Unsatisfied::Version(v.to_string())));
+                    }
                 }
-            })
-            .collect();
-        if is.iter().all(|&i| i) && is.len() == ps.len() {
-            Ok(true)
-        } else {
-            Ok(false)
+                if self.held && !status.held(p) {
+                    not_held.push(p);
+                }
+            }
+            AptReconciliation {
+                unsatisfied,
+                not_held,
+            }
+        })
+    }
+
+    /// Arguments of apt-get install, with exact versions
+    fn install_args(&self, packages: &[&str]) -> Vec<String> {
+        match &self.version {
+            Some(req) if req.op() == Op::Eq => packages
+                .iter()
+                .map(|p| format!("{}={}", p, req.version()))
+                .collect(),
+            _ => packages.iter().map(|p| p.to_string()).collect(),
         }
     }
+}
 
-    fn apply(&self) -> PrResult<()> {
-        let s = process::Command::new("apt-get")
-            .arg("--assume-yes")
-            .arg("install")
-            .args(&self.packages)
-            .status()?;
-        if !s.success() {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Packages {:?} not installed successfully", self.packages),
-            ))
-        } else {
-            Ok(())
+impl Property<os::DebianLike> for AptInstalled {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let AptReconciliation {
+            unsatisfied,
+            not_held,
+        } = self.reconcile()?;
+        for (package, reason) in &unsatisfied {
+            match reason {
+                Unsatisfied::State(state) => println!("Package {} is {}", package, state),
+                Unsatisfied::Version(v) => println!(
+                    "Package {} is version {}, not {}",
+                    package,
+                    v,
+                    self.version
+                        .as_ref()
+                        .expect("Version checked without requirement")
+                ),
+            }
         }
+        if !not_held.is_empty() {
+            println!("Packages not held: {:?}", not_held);
+        }
+        Ok(unsatisfied.is_empty() && not_held.is_empty())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let unsatisfied = self.reconcile()?.unsatisfied;
+        let broken = unsatisfied
+            .iter()
+            .any(|(_, r)| matches!(r, Unsatisfied::State(PackageState::Broken(_))));
+        if broken {
+            // Finish interrupted installations first
+            let s = process::Command::new("dpkg")
+                .arg("--configure")
+                .arg("--pending")
+                .status()?;
+            INSTALLED.invalidate();
+            if !s.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "dpkg failed to configure pending packages",
+                ));
+            }
+        }
+        if !unsatisfied.is_empty() {
+            update_if_stale()?;
+            let packages: Vec<&str> = unsatisfied.iter().map(|(p, _)| *p).collect();
+            // Questions are answered by debconf properties or defaults
+            let mut cmd = process::Command::new("apt-get");
+            cmd.env("DEBIAN_FRONTEND", "noninteractive")
+                .arg("--assume-yes");
+            // Only packages pinned or held by this property may be
+            // downgraded or changed while held
+            if self.version.is_some() {
+                cmd.arg("--allow-downgrades");
+            }
+            if self.held {
+                cmd.arg("--allow-change-held-packages");
+            }
+            let s = cmd
+                .arg("install")
+                .args(self.install_args(&packages))
+                .status()?;
+            INSTALLED.invalidate();
+            if !s.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Packages {:?} not installed successfully", packages),
+                ));
+            }
+        }
+        let AptReconciliation {
+            unsatisfied,
+            not_held,
+        } = self.reconcile()?;
+        if !unsatisfied.is_empty() {
+            let packages: Vec<&str> = unsatisfied.iter().map(|(p, _)| *p).collect();
+            let msg = match &self.version {
+                Some(req) => format!("Sources don't have version {} of {:?}", req, packages),
+                None => format!("Packages {:?} not installed successfully", packages),
+            };
+            return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+        }
+        if !not_held.is_empty() {
+            let s = process::Command::new("apt-mark")
+                .arg("hold")
+                .args(&not_held)
+                .status()?;
+            INSTALLED.invalidate();
+            if !s.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Packages {:?} not held", not_held),
+                ));
+            }
+        }
+        Ok(())
     }
 }
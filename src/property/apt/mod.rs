@@ -1,17 +1,24 @@
 //! Manage packages on Debian and derivatives
 //! apt-get and dpkg may be invoked
 
+mod debconf;
+mod dpkg;
 mod internal;
+mod source;
+mod update;
 
+use self::debconf::AptDebconf;
 use self::internal::{AptInstalled, AptRemoved};
+use self::source::AptSource;
+use self::update::{AptCacheFresh, AptUpgraded};
+use std::time::Duration;
 
 pub fn installed(package: &'static str) -> AptInstalled {
     installed_all(&[package])
 }
 
 pub fn installed_all(packages: &[&'static str]) -> AptInstalled {
-    let packages = packages.to_vec();
-    AptInstalled { packages }
+    AptInstalled::new(packages.to_vec())
 }
 
 pub fn removed(package: &'static str) -> AptRemoved {
@@ -22,3 +29,44 @@ pub fn removed_all(packages: &[&'static str]) -> AptRemoved {
     let packages = packages.to_vec();
     AptRemoved { packages }
 }
+
+/// A source of packages in sources.list.d. Package lists are updated
+/// before installing packages if it changes.
+#[allow(dead_code)]
+pub fn source(name: &'static str) -> AptSource {
+    AptSource::new(name)
+}
+
+/// Package lists were updated within `max_age`, updating them
+/// otherwise
+#[allow(dead_code)]
+pub fn cache_fresh(max_age: Duration) -> AptCacheFresh {
+    AptCacheFresh { max_age }
+}
+
+/// All packages are upgraded with `apt-get dist-upgrade`. Upgrades
+/// are found in the current package lists, so this is usually
+/// combined with `cache_fresh`.
+#[allow(dead_code)]
+pub fn upgraded() -> AptUpgraded {
+    AptUpgraded
+}
+
+/// A debconf question of a package is answered, such as
+/// `debconf("tzdata", "tzdata/Areas", "select", "Europe")`. Answers
+/// are set with `debconf-set-selections` before the package is
+/// installed, or the package is reconfigured if it already is.
+#[allow(dead_code)]
+pub fn debconf(
+    package: &'static str,
+    question: &'static str,
+    kind: &'static str,
+    value: &'static str,
+) -> AptDebconf {
+    AptDebconf {
+        package,
+        question,
+        kind,
+        value,
+    }
+}
@@ -0,0 +1,302 @@
+//! Package sources in sources.list.d and the keyrings they are signed with
+
+use super::update::lists_changed;
+use crate::os;
+use crate::property::Property;
+use crate::util::UserPathBuf;
+use crate::Facts;
+use crate::PrResult;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+/// Installed keyrings must be readable by apt, which drops privileges
+const KEYRING_MODE: u32 = 0o644;
+
+/// A source of packages, written to `sources.list.d/<name>.sources`
+/// in the deb822 format, or to `<name>.list` in the one-line format
+#[derive(Clone)]
+pub struct AptSource {
+    name: &'static str,
+    types: Vec<&'static str>,
+    uris: Vec<&'static str>,
+    suites: Vec<&'static str>,
+    components: Vec<&'static str>,
+    architectures: Vec<&'static str>,
+    /// A local key file installed to `keyrings/`
+    keyring: Option<UserPathBuf>,
+    one_line: bool,
+    apt_dir: PathBuf,
+}
+
+impl AptSource {
+    pub fn new(name: &'static str) -> AptSource {
+        AptSource {
+            name,
+            types: vec!["deb"],
+            uris: vec![],
+            suites: vec![],
+            components: vec![],
+            architectures: vec![],
+            keyring: None,
+            one_line: false,
+            apt_dir: PathBuf::from("/etc/apt"),
+        }
+    }
+
+    /// Add a repository URI
+    pub fn uri(mut self, uri: &'static str) -> AptSource {
+        self.uris.push(uri);
+        self
+    }
+
+    /// Add suites, such as `bookworm`
+    pub fn suites(mut self, suites: &[&'static str]) -> AptSource {
+        self.suites.extend_from_slice(suites);
+        self
+    }
+
+    /// Add components, such as `main`
+    pub fn components(mut self, components: &[&'static str]) -> AptSource {
+        self.components.extend_from_slice(components);
+        self
+    }
+
+    /// Restrict the source to architectures, such as `amd64`
+    #[allow(dead_code)]
+    pub fn architectures(mut self, architectures: &[&'static str]) -> AptSource {
+        self.architectures.extend_from_slice(architectures);
+        self
+    }
+
+    /// Also use source packages
+    #[allow(dead_code)]
+    pub fn with_deb_src(mut self) -> AptSource {
+        self.types.push("deb-src");
+        self
+    }
+
+    /// Sign the source with a key copied from a local file, either
+    /// ASCII-armored (`.asc`) or binary (`.gpg`)
+    pub fn keyring<P: Into<UserPathBuf>>(mut self, key_file: P) -> AptSource {
+        self.keyring = Some(key_file.into());
+        self
+    }
+
+    /// Write a `.list` file with one line per URI and suite
+    #[allow(dead_code)]
+    pub fn one_line(mut self) -> AptSource {
+        self.one_line = true;
+        self
+    }
+
+    /// Use another directory than `/etc/apt`
+    #[allow(dead_code)]
+    pub fn apt_dir<P: Into<PathBuf>>(mut self, dir: P) -> AptSource {
+        self.apt_dir = dir.into();
+        self
+    }
+
+    fn source_path(&self, one_line: bool) -> PathBuf {
+        let ext = if one_line { "list" } else { "sources" };
+        self.apt_dir
+            .join("sources.list.d")
+            .join(format!("{}.{}", self.name, ext))
+    }
+
+    /// Where the key is installed, keeping its extension which apt
+    /// uses to tell the formats apart
+    fn keyring_path(&self) -> io::Result<Option<(PathBuf, PathBuf)>> {
+        let key_file = match &self.keyring {
+            Some(k) => k.expand_user()?,
+            None => return Ok(None),
+        };
+        let ext = match key_file.extension().and_then(|e| e.to_str()) {
+            Some("asc") => "asc",
+            _ => "gpg",
+        };
+        let installed = self
+            .apt_dir
+            .join("keyrings")
+            .join(format!("{}.{}", self.name, ext));
+        Ok(Some((key_file, installed)))
+    }
+
+    fn render(&self) -> io::Result<String> {
+        if self.uris.is_empty() || self.suites.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("apt source {} needs URIs and suites", self.name),
+            ));
+        }
+        let signed_by = self.keyring_path()?.map(|(_, p)| p);
+        let mut out = String::new();
+        if self.one_line {
+            let mut options = vec![];
+            if !self.architectures.is_empty() {
+                options.push(format!("arch={}", self.architectures.join(",")));
+            }
+            if let Some(p) = &signed_by {
+                options.push(format!("signed-by={}", p.to_string_lossy()));
+            }
+            let options = if options.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", options.join(" "))
+            };
+            for t in &self.types {
+                for uri in &self.uris {
+                    for suite in &self.suites {
+                        out.push_str(&format!("{}{} {} {}", t, options, uri, suite));
+                        for component in &self.components {
+                            out.push(' ');
+                            out.push_str(component);
+                        }
+                        out.push('\n');
+                    }
+                }
+            }
+        } else {
+            out.push_str(&format!("Types: {}\n", self.types.join(" ")));
+            out.push_str(&format!("URIs: {}\n", self.uris.join(" ")));
+            out.push_str(&format!("Suites: {}\n", self.suites.join(" ")));
+            if !self.components.is_empty() {
+                out.push_str(&format!("Components: {}\n", self.components.join(" ")));
+            }
+            if !self.architectures.is_empty() {
+                out.push_str(&format!(
+                    "Architectures: {}\n",
+                    self.architectures.join(" ")
+                ));
+            }
+            if let Some(p) = &signed_by {
+                out.push_str(&format!("Signed-By: {}\n", p.to_string_lossy()));
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl fmt::Display for AptSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "apt source {} uses {:?}", self.name, self.uris)?;
+        if let Some(k) = &self.keyring {
+            write!(f, " signed with {:?}", k)?;
+        }
+        Ok(())
+    }
+}
+
+impl Property<os::DebianLike> for AptSource {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        if let Some((key_file, installed)) = self.keyring_path()? {
+            if !installed.exists() || fs::read(&key_file)? != fs::read(&installed)? {
+                return Ok(false);
+            }
+            if fs::metadata(&installed)?.permissions().mode() & 0o777 != KEYRING_MODE {
+                return Ok(false);
+            }
+        }
+        if self.source_path(!self.one_line).exists() {
+            return Ok(false);
+        }
+        let path = self.source_path(self.one_line);
+        if !path.exists() {
+            return Ok(false);
+        }
+        Ok(fs::read_to_string(&path)? == self.render()?)
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let content = self.render()?;
+        if let Some((key_file, installed)) = self.keyring_path()? {
+            if let Some(dir) = installed.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            println!("Installing key {:?} to {:?}", key_file, installed);
+            // The mode of the key file is copied too
+            fs::copy(&key_file, &installed)?;
+            fs::set_permissions(&installed, fs::Permissions::from_mode(KEYRING_MODE))?;
+        }
+        let path = self.source_path(self.one_line);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, content)?;
+        // The source in the other format would be a duplicate
+        let other = self.source_path(!self.one_line);
+        if other.exists() {
+            fs::remove_file(other)?;
+        }
+        lists_changed();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property::apt::source;
+    use std::env::temp_dir;
+
+    #[test]
+    fn test_apt_source() {
+        let dir = temp_dir().join("apt_source_test");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        let key = dir.join("docker.asc");
+        fs::write(&key, "key").unwrap();
+        fs::set_permissions(&key, fs::Permissions::from_mode(0o600)).unwrap();
+        let apt_dir = dir.join("apt");
+        let docker = source("docker")
+            .uri("https://download.docker.com/linux/debian")
+            .suites(&["bookworm"])
+            .components(&["stable"])
+            .architectures(&["amd64"])
+            .keyring(&key)
+            .apt_dir(&apt_dir);
+        let keyring = apt_dir.join("keyrings/docker.asc");
+        assert_eq!(
+            docker.render().unwrap(),
+            format!(
+                "Types: deb\n\
+                 URIs: https://download.docker.com/linux/debian\n\
+                 Suites: bookworm\n\
+                 Components: stable\n\
+                 Architectures: amd64\n\
+                 Signed-By: {}\n",
+                keyring.to_string_lossy()
+            )
+        );
+        let prop: Box<dyn Property<os::DebianLike>> = Box::new(docker.clone());
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
+        assert_eq!(fs::read(&keyring).unwrap(), b"key");
+        let mode = fs::metadata(&keyring).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, KEYRING_MODE);
+        fs::set_permissions(&keyring, fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
+        assert!(source("empty").render().is_err());
+
+        let one_line = docker.one_line();
+        assert_eq!(
+            one_line.render().unwrap(),
+            format!(
+                "deb [arch=amd64 signed-by={}] https://download.docker.com/linux/debian bookworm stable\n",
+                keyring.to_string_lossy()
+            )
+        );
+        let prop: Box<dyn Property<os::DebianLike>> = Box::new(one_line);
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
+        assert!(!apt_dir.join("sources.list.d/docker.sources").exists());
+    }
+}
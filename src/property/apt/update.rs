@@ -0,0 +1,182 @@
+//! Package lists and upgrades. Package lists are updated before
+//! installing if they have never been updated, or if sources changed.
+
+use super::internal::installed_changed;
+use crate::os;
+use crate::property::installed::query_output;
+use crate::property::Property;
+use crate::Facts;
+use crate::PrResult;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+const LISTS_DIR: &str = "/var/lib/apt/lists";
+
+/// Sources have changed since package lists were last updated
+static LISTS_STALE: AtomicBool = AtomicBool::new(false);
+
+/// Package lists need to be updated before installing packages
+pub(super) fn lists_changed() {
+    LISTS_STALE.store(true, Ordering::SeqCst);
+}
+
+/// Time since package lists were last updated, or `None` if there
+/// are no lists, such as in a fresh container
+fn lists_age(dir: &Path) -> io::Result<Option<Duration>> {
+    let mut newest = None;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "partial" || name == "lock" {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if newest.is_none_or(|n| modified > n) {
+            newest = Some(modified);
+        }
+    }
+    // Lists from the future are fresh
+    Ok(newest.map(|n| {
+        SystemTime::now()
+            .duration_since(n)
+            .unwrap_or(Duration::from_secs(0))
+    }))
+}
+
+fn update() -> io::Result<()> {
+    println!("Updating package lists");
+    let s = process::Command::new("apt-get").arg("update").status()?;
+    if !s.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "apt-get update failed",
+        ));
+    }
+    LISTS_STALE.store(false, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Update package lists if sources have changed or lists are missing
+pub(super) fn update_if_stale() -> io::Result<()> {
+    let missing = match lists_age(Path::new(LISTS_DIR)) {
+        Ok(age) => age.is_none(),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => true,
+        Err(e) => return Err(e),
+    };
+    if missing || LISTS_STALE.load(Ordering::SeqCst) {
+        update()?;
+    }
+    Ok(())
+}
+
+/// Package lists were updated within a maximum age
+#[derive(Clone)]
+pub struct AptCacheFresh {
+    pub max_age: Duration,
+}
+
+impl fmt::Display for AptCacheFresh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "apt package lists are updated within {}s",
+            self.max_age.as_secs()
+        )
+    }
+}
+
+impl Property<os::DebianLike> for AptCacheFresh {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        if LISTS_STALE.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+        match lists_age(Path::new(LISTS_DIR)) {
+            Ok(Some(age)) => Ok(age <= self.max_age),
+            Ok(None) => Ok(false),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        update()
+    }
+}
+
+/// All packages are upgraded to the versions in the package lists,
+/// installing and removing packages as needed
+#[derive(Clone)]
+pub struct AptUpgraded;
+
+/// Packages which would be upgraded, from a simulated upgrade
+fn pending_upgrades() -> io::Result<Vec<String>> {
+    let out = query_output(
+        process::Command::new("apt-get")
+            .arg("--simulate")
+            .arg("dist-upgrade"),
+    )?;
+    // Such as "Inst libc6 [2.36-9] (2.36-9+deb12u1 Debian:12.1/stable [amd64])"
+    Ok(out
+        .lines()
+        .filter_map(|l| l.strip_prefix("Inst "))
+        .filter_map(|l| l.split_whitespace().next())
+        .map(|p| p.to_string())
+        .collect())
+}
+
+impl fmt::Display for AptUpgraded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "packages are upgraded by apt")
+    }
+}
+
+impl Property<os::DebianLike> for AptUpgraded {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let pending = pending_upgrades()?;
+        if !pending.is_empty() {
+            println!("Packages to upgrade: {:?}", pending);
+        }
+        Ok(pending.is_empty())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        update_if_stale()?;
+        let s = process::Command::new("apt-get")
+            .env("DEBIAN_FRONTEND", "noninteractive")
+            .arg("--assume-yes")
+            .arg("dist-upgrade")
+            .status()?;
+        installed_changed();
+        if !s.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "apt-get dist-upgrade failed",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lists_age() {
+        let dir = std::env::temp_dir().join("rotor_test_apt_lists");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("partial")).unwrap();
+        fs::write(dir.join("lock"), "").unwrap();
+        assert_eq!(lists_age(&dir).unwrap(), None);
+
+        fs::write(dir.join("deb.debian.org_debian_dists_stable_InRelease"), "").unwrap();
+        let age = lists_age(&dir).unwrap().unwrap();
+        assert!(age < Duration::from_secs(60));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
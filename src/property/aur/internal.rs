@@ -0,0 +1,247 @@
+use crate::os;
+use crate::property::pacman::{installed_changed, query_names};
+use crate::property::Property;
+use crate::util::passwd::Passwd;
+use crate::util::UserPathBuf;
+use crate::Facts;
+use crate::PrResult;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const AUR_URL: &str = "https://aur.archlinux.org";
+/// Default build directory, in the home of the build user
+const BUILD_DIR: &str = ".cache/rotor/aur";
+
+/// How packages are built and installed
+#[derive(Clone, Debug)]
+enum AurBuilder {
+    /// Clone the package from the AUR and run makepkg, which must be
+    /// run as an unprivileged user. When running as root, the
+    /// package is built as the given user and installed by pacman.
+    Makepkg { user: Option<&'static str> },
+    /// A helper program with the same options as pacman, run as
+    /// the given user if any
+    Helper {
+        program: &'static str,
+        user: Option<&'static str>,
+    },
+}
+
+/// Packages from the AUR are installed, as foreign packages
+#[derive(Clone)]
+pub struct AurInstalled {
+    pub packages: Vec<&'static str>,
+    builder: AurBuilder,
+    /// Where packages are cloned and built, by default in the
+    /// home of the build user
+    build_dir: Option<UserPathBuf>,
+}
+
+impl AurInstalled {
+    pub fn new(packages: Vec<&'static str>) -> AurInstalled {
+        AurInstalled {
+            packages,
+            builder: AurBuilder::Makepkg { user: None },
+            build_dir: None,
+        }
+    }
+
+    /// Install with a helper such as `paru` or `yay` instead of makepkg
+    #[allow(dead_code)]
+    pub fn with_helper(mut self, program: &'static str) -> AurInstalled {
+        self.builder = AurBuilder::Helper {
+            program,
+            user: self.build_user(),
+        };
+        self
+    }
+
+    /// Build packages as this user, which is needed when running
+    /// as root. The user needs sudo for helpers to install packages.
+    #[allow(dead_code)]
+    pub fn build_as(mut self, user: &'static str) -> AurInstalled {
+        match &mut self.builder {
+            AurBuilder::Makepkg { user: u } => *u = Some(user),
+            AurBuilder::Helper { user: u, .. } => *u = Some(user),
+        }
+        self
+    }
+
+    /// Clone and build packages in this directory instead of
+    /// `~/.cache/rotor/aur` of the build user. It must be writable
+    /// by the build user.
+    #[allow(dead_code)]
+    pub fn build_in<P: Into<UserPathBuf>>(mut self, dir: P) -> AurInstalled {
+        self.build_dir = Some(dir.into());
+        self
+    }
+
+    fn build_user(&self) -> Option<&'static str> {
+        match self.builder {
+            AurBuilder::Makepkg { user } => user,
+            AurBuilder::Helper { user, .. } => user,
+        }
+    }
+
+    /// The build directory, created by the build user so that it
+    /// can write to it
+    fn build_dir(&self) -> io::Result<PathBuf> {
+        let user = self.build_user();
+        let dir = match (&self.build_dir, user) {
+            (Some(dir), _) => dir.expand_user()?,
+            (None, Some(user)) => match Passwd::by_name(user)? {
+                Some(account) => account.home.join(BUILD_DIR),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Build user {} doesn't exist", user),
+                    ))
+                }
+            },
+            (None, None) => UserPathBuf::from(BUILD_DIR).expand_user()?,
+        };
+        match user {
+            Some(_) => {
+                let path = dir.to_string_lossy();
+                run(user, Path::new("/"), "mkdir", &["-p", &path])?;
+            }
+            None => fs::create_dir_all(&dir)?,
+        }
+        Ok(dir)
+    }
+
+    /// Packages not installed as foreign packages
+    fn missing(&self) -> io::Result<Vec<&'static str>> {
+        let foreign = query_names(&["-Qqm"])?;
+        Ok(self
+            .packages
+            .iter()
+            .filter(|&&p| !foreign.iter().any(|f| f == p))
+            .cloned()
+            .collect())
+    }
+
+    fn makepkg(&self, package: &str, user: Option<&str>) -> io::Result<()> {
+        let build_dir = self.build_dir()?;
+        let pkg_dir = build_dir.join(package);
+        if pkg_dir.join(".git").is_dir() {
+            run(
+                user,
+                &build_dir,
+                "git",
+                &["-C", package, "pull", "--ff-only"],
+            )?;
+        } else {
+            let url = format!("{}/{}.git", AUR_URL, package);
+            run(user, &build_dir, "git", &["clone", &url])?;
+        }
+        if !pkg_dir.join("PKGBUILD").exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a package in the AUR", package),
+            ));
+        }
+        match user {
+            None => run(
+                None,
+                &pkg_dir,
+                "makepkg",
+                &["-si", "--needed", "--noconfirm"],
+            ),
+            Some(user) => {
+                // Dependencies can't be installed without root, so they
+                // are expected to be installed already
+                run(Some(user), &pkg_dir, "makepkg", &["--noconfirm", "--force"])?;
+                let out = process::Command::new("runuser")
+                    .args(["-u", user, "--", "makepkg", "--packagelist"])
+                    .current_dir(&pkg_dir)
+                    .output()?;
+                if !out.status.success() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!(
+                            "makepkg --packagelist failed: {}",
+                            String::from_utf8_lossy(&out.stderr).trim()
+                        ),
+                    ));
+                }
+                let files = String::from_utf8_lossy(&out.stdout).into_owned();
+                let files: Vec<&str> = files.lines().filter(|f| Path::new(f).exists()).collect();
+                let mut args = vec!["-U", "--needed", "--noconfirm"];
+                args.extend(files);
+                run(None, &pkg_dir, "pacman", &args)
+            }
+        }
+    }
+}
+
+/// Run a program with its output shown, optionally as another user
+fn run(user: Option<&str>, dir: &Path, program: &str, args: &[&str]) -> io::Result<()> {
+    let mut cmd = match user {
+        Some(user) => {
+            let mut cmd = process::Command::new("runuser");
+            cmd.args(["-u", user, "--", program]);
+            cmd
+        }
+        None => process::Command::new(program),
+    };
+    let s = cmd.args(args).current_dir(dir).status()?;
+    if !s.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{} {} failed", program, args.join(" ")),
+        ));
+    }
+    Ok(())
+}
+
+impl fmt::Display for AurInstalled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.packages.len() == 1 {
+            write!(f, "package {} is installed from the AUR", self.packages[0])
+        } else {
+            write!(f, "packages {:?} are installed from the AUR", self.packages)
+        }
+    }
+}
+
+impl Property<os::ArchLinux> for AurInstalled {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        Ok(self.missing()?.is_empty())
+    }
+
+    fn apply(&self, facts: &Facts) -> PrResult<()> {
+        match self.builder {
+            AurBuilder::Helper { program, .. } if !facts.has_command(program) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("AUR helper {} is not installed", program),
+                ));
+            }
+            AurBuilder::Makepkg { user: None } if facts.is_root() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "makepkg can't run as root, build as another user with build_as",
+                ));
+            }
+            _ => {}
+        }
+        let missing = self.missing()?;
+        let result = match self.builder {
+            AurBuilder::Helper { program, user } => {
+                let mut args = vec!["-S", "--needed", "--noconfirm"];
+                args.extend(&missing);
+                let dir = self.build_dir()?;
+                run(user, &dir, program, &args)
+            }
+            AurBuilder::Makepkg { user } => missing
+                .iter()
+                .try_for_each(|package| self.makepkg(package, user)),
+        };
+        installed_changed();
+        result
+    }
+}
@@ -0,0 +1,18 @@
+//! Packages from the Arch User Repository, built with makepkg or
+//! installed with a helper such as paru or yay
+
+mod internal;
+
+use self::internal::AurInstalled;
+
+/// Package from the AUR is installed
+#[allow(dead_code)]
+pub fn installed(package: &'static str) -> AurInstalled {
+    installed_all(&[package])
+}
+
+/// Packages from the AUR are installed
+#[allow(dead_code)]
+pub fn installed_all(packages: &[&'static str]) -> AurInstalled {
+    AurInstalled::new(packages.to_vec())
+}
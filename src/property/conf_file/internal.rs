@@ -1,4 +1,5 @@
 use crate::util::UserPathBuf;
+use crate::Facts;
 use crate::PrResult;
 use crate::{os::Any, property::Property};
 use std::collections::{HashMap, HashSet};
@@ -64,7 +65,7 @@ fn line_key_value(line: &str, comment: char, equal: char) -> Option<(&str, &str)
 }
 
 impl Property<Any> for ConfFileAssignments {
-    fn check(&self) -> PrResult<bool> {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
         let mut needed: HashSet<&str> = self.assignments.keys().map(|s| s.as_ref()).collect();
         let path = self.file.path.expand_user()?;
         let contents = fs::read_to_string(&path)?;
@@ -82,7 +83,7 @@ impl Property<Any> for ConfFileAssignments {
         Ok(needed.is_empty())
     }
 
-    fn apply(&self) -> PrResult<()> {
+    fn apply(&self, _: &Facts) -> PrResult<()> {
         let path = self.file.path.expand_user()?;
         let contents = fs::read_to_string(&path)?;
         let mut needed: HashSet<&str> = self.assignments.keys().map(|s| s.as_ref()).collect();
@@ -117,6 +118,10 @@ impl Property<Any> for ConfFileAssignments {
         writer.flush()?;
         Ok(())
     }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
 }
 
 fn char_bytes(c: char) -> Vec<u8> {
@@ -4,17 +4,17 @@ use crate::os::OS;
 use crate::property::Property;
 
 pub trait PropertyClone<O> {
-    fn clone_box(&self) -> Box<Property<O>>;
+    fn clone_box(&self) -> Box<dyn Property<O>>;
 }
 
 impl<P: 'static + Property<O> + Clone, O: OS> PropertyClone<O> for P {
-    fn clone_box(&self) -> Box<Property<O>> {
+    fn clone_box(&self) -> Box<dyn Property<O>> {
         Box::new(self.clone())
     }
 }
 
-impl<O: OS> Clone for Box<Property<O>> {
-    fn clone(&self) -> Box<Property<O>> {
+impl<O: OS> Clone for Box<dyn Property<O>> {
+    fn clone(&self) -> Box<dyn Property<O>> {
         self.clone_box()
     }
 }
@@ -0,0 +1,292 @@
+//! Whether a directory and its entries exist
+
+use crate::os::Any;
+use crate::property::Property;
+use crate::util::{home_dir, UserPathBuf};
+use crate::Facts;
+use crate::PrResult;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The directory exists, with its parents
+#[derive(Clone)]
+pub struct DirExists {
+    pub path: Arc<UserPathBuf>,
+    mode: Option<u32>,
+}
+
+/// Nothing exists at the path of the directory
+#[derive(Clone)]
+pub struct DirAbsent {
+    pub path: Arc<UserPathBuf>,
+}
+
+/// The directory contains nothing but the given entries
+#[derive(Clone)]
+pub struct DirPurged {
+    pub path: Arc<UserPathBuf>,
+    kept: Vec<&'static str>,
+}
+
+impl DirExists {
+    pub fn new(path: Arc<UserPathBuf>) -> DirExists {
+        DirExists { path, mode: None }
+    }
+
+    /// Permission bits of the directory, such as `0o700`.
+    /// Parents created along the way get the default mode.
+    #[allow(dead_code)]
+    pub fn mode(mut self, mode: u32) -> DirExists {
+        self.mode = Some(mode);
+        self
+    }
+}
+
+impl DirAbsent {
+    pub fn new(path: Arc<UserPathBuf>) -> DirAbsent {
+        DirAbsent { path }
+    }
+}
+
+impl DirPurged {
+    pub fn new(path: Arc<UserPathBuf>, kept: &[&'static str]) -> DirPurged {
+        DirPurged {
+            path,
+            kept: kept.to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for DirExists {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Directory {:?} exists", self.path)?;
+        if let Some(mode) = self.mode {
+            write!(f, " with mode {:o}", mode)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DirAbsent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Directory {:?} is absent", self.path)
+    }
+}
+
+impl fmt::Display for DirPurged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Directory {:?} contains nothing but {:?}",
+            self.path, self.kept
+        )
+    }
+}
+
+impl Property<Any> for DirExists {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let path = self.path.expand_user()?;
+        if !path.is_dir() {
+            return Ok(false);
+        }
+        match self.mode {
+            Some(mode) => Ok(get_mode(&path)? == mode),
+            None => Ok(true),
+        }
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let path = self.path.expand_user()?;
+        if !path.is_dir() {
+            println!("Creating directory {:?}", path);
+            fs::create_dir_all(&path)?;
+        }
+        if let Some(mode) = self.mode {
+            set_mode(&path, mode)?;
+        }
+        Ok(())
+    }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
+}
+
+impl Property<Any> for DirAbsent {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let path = self.path.expand_user()?;
+        Ok(!exists_or_link(&path)?)
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let path = self.path.expand_user()?;
+        ensure_removable(&path)?;
+        let meta = path.symlink_metadata()?;
+        if meta.is_dir() {
+            println!("Removing directory {:?}", path);
+            fs::remove_dir_all(&path)
+        } else {
+            // Only the link is removed, not what it points to
+            println!("Removing {:?}", path);
+            fs::remove_file(&path)
+        }
+    }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
+}
+
+impl Property<Any> for DirPurged {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let path = self.path.expand_user()?;
+        for entry in fs::read_dir(&path)? {
+            let name = entry?.file_name();
+            if !self.kept.iter().any(|&k| name == k) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let path = self.path.expand_user()?;
+        ensure_removable(&path)?;
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if self.kept.iter().any(|&k| name == k) {
+                continue;
+            }
+            println!("Removing {:?} from {:?}", name, path);
+            if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
+}
+
+/// Whether anything, including a broken symlink, is at the path
+fn exists_or_link(path: &Path) -> io::Result<bool> {
+    match path.symlink_metadata() {
+        Ok(_) => Ok(true),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Refuse to remove the root directory, the home directory or
+/// any directory containing the home directory
+fn ensure_removable(path: &Path) -> io::Result<()> {
+    let path = match path.canonicalize() {
+        Ok(p) => p,
+        // A broken symlink can be removed
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let protected = match home_dir() {
+        Some(home) => home.canonicalize().unwrap_or(home).starts_with(&path),
+        None => false,
+    };
+    if path.parent().is_none() || protected {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Refusing to remove {:?}", path),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn get_mode(path: &Path) -> io::Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::metadata(path)?.permissions().mode() & 0o7777)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(windows)]
+/// Permission bits are not supported
+fn get_mode(_path: &Path) -> io::Result<u32> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Directory mode is not supported",
+    ))
+}
+
+#[cfg(windows)]
+/// Permission bits are not supported
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Directory mode is not supported",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property::dir::path;
+    use std::env::temp_dir;
+
+    #[test]
+    fn test_dir_exists_absent() {
+        let base = temp_dir().join("dir_exists_absent_test");
+        let dir = base.join("a/b");
+        if base.exists() {
+            fs::remove_dir_all(&base).unwrap();
+        }
+        let exists: Box<dyn Property<Any>> = Box::new(path(&dir).exists().mode(0o700));
+        let absent: Box<dyn Property<Any>> = Box::new(path(base.join("a")).absent());
+        assert!(!exists.check(&Facts::default()).unwrap());
+        assert!(absent.check(&Facts::default()).unwrap());
+        exists.apply(&Facts::default()).unwrap();
+        assert!(exists.check(&Facts::default()).unwrap());
+        assert!(!absent.check(&Facts::default()).unwrap());
+        absent.apply(&Facts::default()).unwrap();
+        assert!(absent.check(&Facts::default()).unwrap());
+        assert!(base.is_dir());
+    }
+
+    #[test]
+    fn test_dir_purged() {
+        let dir = temp_dir().join("dir_purged_test");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        for sub in &["Documents", "Music/album", "Pictures"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("notes"), b"").unwrap();
+        let purged: Box<dyn Property<Any>> =
+            Box::new(path(&dir).purged_except(&["Documents", "notes"]));
+        assert!(!purged.check(&Facts::default()).unwrap());
+        purged.apply(&Facts::default()).unwrap();
+        assert!(purged.check(&Facts::default()).unwrap());
+        assert!(dir.join("Documents").is_dir());
+        assert!(dir.join("notes").is_file());
+        assert!(!dir.join("Music").exists());
+    }
+
+    #[test]
+    fn test_removal_guard() {
+        assert!(ensure_removable(Path::new("/")).is_err());
+        if let Some(home) = home_dir() {
+            assert!(ensure_removable(&home).is_err());
+        }
+    }
+}
@@ -1,10 +1,14 @@
 use crate::os::Any;
 use crate::property::Property;
 use crate::util::UserPathBuf;
+use crate::Facts;
 use crate::PrResult;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fmt;
 use std::fs;
 use std::io;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -29,8 +33,22 @@ pub struct PackageLinked {
     /// Directory where directories and symlinks should
     /// be created.
     install: Option<UserPathBuf>,
+    /// Installation directories of packages that are not
+    /// installed to the common one.
+    targets: BTreeMap<&'static str, UserPathBuf>,
+    /// Suffixes of package variants overlaid on packages,
+    /// later ones being more specific.
+    variants: Vec<&'static str>,
     linked: Vec<&'static str>,
     unlinked: Vec<&'static str>,
+    /// Remove broken symlinks into the repo and directories
+    /// left empty by removing links.
+    prune: bool,
+    /// Remove links into linked packages whose source files
+    /// no longer exist.
+    restow: bool,
+    /// Link every package in the repo which is not unlinked.
+    link_rest: bool,
 }
 
 impl PackageLinked {
@@ -40,8 +58,13 @@ impl PackageLinked {
         PackageLinked {
             repo: path,
             install: target,
+            targets: BTreeMap::new(),
+            variants: vec![],
             linked: vec![],
             unlinked: vec![],
+            prune: false,
+            restow: false,
+            link_rest: false,
         }
     }
 
@@ -55,6 +78,30 @@ impl PackageLinked {
         self
     }
 
+    /// Set the directory where directories and symlinks of
+    /// a single package should be created, such as `/etc`.
+    #[allow(dead_code)]
+    pub fn install_package_to<P: Into<UserPathBuf>>(
+        mut self,
+        package: &'static str,
+        target: P,
+    ) -> PackageLinked {
+        self.targets.insert(package, target.into());
+        self
+    }
+
+    /// Overlay variants of packages, such as `vim@laptop` for
+    /// the package `vim` with variant `laptop`. Files in the
+    /// variant are linked instead of files with the same path in
+    /// the package, and files only in the variant are added.
+    /// Variants added later are more specific. Variants that
+    /// don't exist for a package are ignored.
+    #[allow(dead_code)]
+    pub fn variant(mut self, variant: &'static str) -> PackageLinked {
+        self.variants.push(variant);
+        self
+    }
+
     #[allow(dead_code)]
     /// Add a package to be linked.
     pub fn linked(self, package: &'static str) -> PackageLinked {
@@ -79,12 +126,93 @@ impl PackageLinked {
         self
     }
 
+    /// Link every package found in the repo that is not explicitly
+    /// unlinked, so that new packages are not silently ignored.
+    /// Hidden directories such as `.git` are not packages.
+    #[allow(dead_code)]
+    pub fn linked_rest(mut self) -> PackageLinked {
+        self.link_rest = true;
+        self
+    }
+
+    /// Also remove broken symlinks pointing into the repo, such as
+    /// those left behind after renaming files in a package, and
+    /// delete directories which held only removed links. Top-level
+    /// directories are only searched if a package has one of the
+    /// same name.
+    #[allow(dead_code)]
+    pub fn pruned(mut self) -> PackageLinked {
+        self.prune = true;
+        self
+    }
+
+    /// Reconcile linked packages with the repo: symlinks in the
+    /// installation directory pointing into a linked package whose
+    /// source files have been deleted or renamed are removed.
+    /// Directories mirroring the top-level directories of the package
+    /// are searched recursively.
+    #[allow(dead_code)]
+    pub fn restowed(mut self) -> PackageLinked {
+        self.restow = true;
+        self
+    }
+
     fn get_repo_dir(&self) -> io::Result<PathBuf> {
         self.repo.expand_user()?.canonicalize()
     }
 
-    fn get_install_dir(&self) -> io::Result<PathBuf> {
-        if let Some(p) = &self.install {
+    /// Packages to be linked, including discovered ones when
+    /// linking the rest
+    fn linked_packages(&self, repo: &Path) -> io::Result<Vec<String>> {
+        let mut linked: Vec<String> = self.linked.iter().map(|p| p.to_string()).collect();
+        if self.link_rest {
+            linked.extend(self.unlisted_packages(repo)?);
+        }
+        Ok(linked)
+    }
+
+    /// Packages in the repo that are neither linked nor unlinked
+    fn unlisted_packages(&self, repo: &Path) -> io::Result<Vec<String>> {
+        let mut unlisted = vec![];
+        for package in list_packages(repo)? {
+            let listed = self.linked.contains(&package.as_str())
+                || self.unlinked.contains(&package.as_str());
+            if !listed {
+                unlisted.push(package);
+            }
+        }
+        Ok(unlisted)
+    }
+
+    /// Locate the package with its variants and where it
+    /// should be installed
+    fn package(&self, repo: &Path, name: &str) -> io::Result<Package> {
+        let mut layers = vec![repo.join(name)];
+        for variant in &self.variants {
+            let layer = repo.join(format!("{}@{}", name, variant));
+            if layer.is_dir() {
+                layers.push(layer);
+            }
+        }
+        let install = self.get_install_dir(name)?;
+        Ok(Package {
+            name: name.to_string(),
+            layers,
+            install,
+        })
+    }
+
+    fn packages<S: AsRef<str>>(&self, repo: &Path, names: &[S]) -> io::Result<Vec<Package>> {
+        names
+            .iter()
+            .map(|name| self.package(repo, name.as_ref()))
+            .collect()
+    }
+
+    fn get_install_dir(&self, package: &str) -> io::Result<PathBuf> {
+        if let Some(p) = self.targets.get(package) {
+            p.expand_user()
+        } else if let Some(p) = &self.install {
             p.expand_user()
         } else {
             Err(io::Error::new(
@@ -96,76 +224,351 @@ impl PackageLinked {
 }
 
 impl Property<Any> for PackageLinked {
-    fn check(&self) -> PrResult<bool> {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
         let source = self.get_repo_dir()?;
-        let target = self.get_install_dir()?;
-        for &package in &self.linked {
-            if !check_linked(&source, &target, package)? {
+        if !self.link_rest {
+            let unlisted = self.unlisted_packages(&source)?;
+            if !unlisted.is_empty() {
+                eprintln!(
+                    "Packages {:?} in {:?} are neither linked nor unlinked",
+                    unlisted, self.repo
+                );
+            }
+        }
+        let linked = self.packages(&source, &self.linked_packages(&source)?)?;
+        let unlinked = self.packages(&source, &self.unlinked)?;
+        for package in &linked {
+            if !check_linked(package)? {
+                return Ok(false);
+            }
+            if self.restow && !stale_links(package)?.is_empty() {
                 return Ok(false);
             }
         }
-        for &package in &self.unlinked {
-            if !check_unlinked(&source, &target, package)? {
+        for package in &unlinked {
+            if !check_unlinked(package)? {
                 return Ok(false);
             }
         }
+        if self.prune {
+            for package in &linked {
+                if !check_pruned(&source, package, false)? {
+                    return Ok(false);
+                }
+            }
+            for package in &unlinked {
+                if !check_pruned(&source, package, true)? {
+                    return Ok(false);
+                }
+            }
+        }
         Ok(true)
     }
 
-    fn apply(&self) -> PrResult<()> {
+    fn apply(&self, _: &Facts) -> PrResult<()> {
         let source = self.get_repo_dir()?;
-        let target = self.get_install_dir()?;
-        for &package in &self.linked {
-            if !check_linked(&source, &target, package)? {
-                println!("Linking package {}", package);
-                link_package(&source, &target, package)?;
+        let linked = self.packages(&source, &self.linked_packages(&source)?)?;
+        let unlinked = self.packages(&source, &self.unlinked)?;
+        // Nothing is changed unless all packages can be linked
+        let conflicts = self.plan(&source, &linked, &unlinked)?;
+        if !conflicts.is_empty() {
+            for conflict in &conflicts {
+                eprintln!("Conflict: {}", conflict);
             }
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} conflicts, no packages linked", conflicts.len()),
+            ));
         }
-        for &package in &self.unlinked {
-            if !check_unlinked(&source, &target, package)? {
-                println!("Unlinking package {}", package);
-                unlink_package(&source, &target, package)?
+        if self.prune {
+            // Broken links would otherwise stop packages from being linked
+            for package in &linked {
+                prune_package(&source, package, false)?;
+            }
+        }
+        // Links of unlinked packages may be in the way of linked ones
+        for package in &unlinked {
+            if !check_unlinked(package)? {
+                println!("Unlinking package {}", package.name);
+                // Pruning removes the links itself, so that directories
+                // holding only them are known to be left empty
+                if !self.prune {
+                    unlink_package(package)?
+                }
+            }
+            if self.prune {
+                prune_package(&source, package, true)?;
+            }
+        }
+        for package in &linked {
+            if self.restow {
+                for link in stale_links(package)? {
+                    println!(
+                        "Removing stale symlink {:?} of package {}",
+                        link, package.name
+                    );
+                    fs::remove_file(&link)?;
+                }
+            }
+            if !check_linked(package)? {
+                println!("Linking package {}", package.name);
+                link_package(package)?;
             }
         }
         Ok(())
     }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
+}
+
+/// A reason why a file or directory of a package can't be linked
+#[derive(Debug, PartialEq)]
+enum Conflict {
+    /// A regular file or directory is in the way
+    Occupied { path: PathBuf, package: String },
+    /// A symlink pointing outside of the package is in the way
+    ForeignLink {
+        path: PathBuf,
+        package: String,
+        target: PathBuf,
+    },
+    /// A broken symlink which won't be removed is in the way
+    BrokenLink { path: PathBuf, package: String },
+    /// More than one package provides the same file
+    Duplicate {
+        path: PathBuf,
+        packages: (String, String),
+    },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Conflict::Occupied { path, package } => {
+                write!(f, "{:?} exists, can't create symlink for {}", path, package)
+            }
+            Conflict::ForeignLink {
+                path,
+                package,
+                target,
+            } => write!(
+                f,
+                "{:?} is a symlink to {:?}, can't create symlink for {}",
+                path, target, package
+            ),
+            Conflict::BrokenLink { path, package } => write!(
+                f,
+                "{:?} is a broken symlink, can't create symlink for {}",
+                path, package
+            ),
+            Conflict::Duplicate {
+                path,
+                packages: (a, b),
+            } => write!(f, "{:?} is provided by both {} and {}", path, a, b),
+        }
+    }
+}
+
+impl PackageLinked {
+    /// Find everything that would stop the linked packages from being
+    /// linked, without changing anything. Symlinks that would be
+    /// removed by unlinking, pruning or restowing are not conflicts.
+    fn plan(
+        &self,
+        repo: &Path,
+        linked: &[Package],
+        unlinked: &[Package],
+    ) -> io::Result<Vec<Conflict>> {
+        let mut conflicts = vec![];
+        // Installation paths of files and the packages providing them
+        let mut provided: HashMap<PathBuf, &str> = HashMap::new();
+        for package in linked {
+            // Directories occupied by something else; their contents
+            // can't be checked
+            let mut occupied: Vec<PathBuf> = vec![];
+            for entry in package.entries()? {
+                let path = package.install.join(&entry.rel_path);
+                if occupied.iter().any(|dir| path.starts_with(dir)) {
+                    continue;
+                }
+                if entry.is_dir {
+                    if path.symlink_metadata().is_ok() && !path.is_dir() {
+                        occupied.push(path.clone());
+                        conflicts.push(Conflict::Occupied {
+                            path,
+                            package: package.name.clone(),
+                        });
+                    }
+                    continue;
+                }
+                if let Some(other) = provided.insert(path.clone(), &package.name) {
+                    conflicts.push(Conflict::Duplicate {
+                        path,
+                        packages: (other.to_string(), package.name.clone()),
+                    });
+                    continue;
+                }
+                let meta = match path.symlink_metadata() {
+                    Ok(meta) => meta,
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+                if !meta.file_type().is_symlink() {
+                    conflicts.push(Conflict::Occupied {
+                        path,
+                        package: package.name.clone(),
+                    });
+                    continue;
+                }
+                let target = link_target(&path)?;
+                let conflict = if path.exists() {
+                    let removed = package.provides(&target)
+                        || unlinked.iter().any(|p| p.provides(&target))
+                        || is_same_file(&path, &entry.source)?;
+                    if removed {
+                        None
+                    } else {
+                        Some(Conflict::ForeignLink {
+                            path,
+                            package: package.name.clone(),
+                            target,
+                        })
+                    }
+                } else {
+                    let removed = (self.prune && target.starts_with(repo))
+                        || (self.restow && package.provides(&target));
+                    if removed {
+                        None
+                    } else {
+                        Some(Conflict::BrokenLink {
+                            path,
+                            package: package.name.clone(),
+                        })
+                    }
+                };
+                conflicts.extend(conflict);
+            }
+        }
+        Ok(conflicts)
+    }
+}
+
+/// A package located in the repo, with the directory it is
+/// installed to
+struct Package {
+    name: String,
+    /// The package directory followed by its variants. Files in
+    /// later directories take precedence.
+    layers: Vec<PathBuf>,
+    install: PathBuf,
+}
+
+/// A file or directory in a package
+struct PackageEntry {
+    /// Relative path. Should be the same for the file relative
+    /// to the package directory and the link relative to the
+    /// installation directory.
+    rel_path: PathBuf,
+    /// Path in the layer providing the entry
+    source: PathBuf,
+    is_dir: bool,
+}
+
+impl Package {
+    /// Files and directories of the package with variants overlaid,
+    /// sorted so that directories come before their contents.
+    fn entries(&self) -> io::Result<Vec<PackageEntry>> {
+        let mut merged = BTreeMap::new();
+        for layer in &self.layers {
+            for entry in layer_entries(layer)? {
+                merged.insert(entry.rel_path.clone(), entry);
+            }
+        }
+        Ok(merged.into_values().collect())
+    }
+
+    /// Files and directories in all layers, including files
+    /// overridden by variants.
+    fn all_entries(&self) -> io::Result<Vec<PackageEntry>> {
+        let mut entries = vec![];
+        for layer in &self.layers {
+            entries.extend(layer_entries(layer)?);
+        }
+        Ok(entries)
+    }
+
+    /// Whether the path is inside one of the layers
+    fn provides(&self, path: &Path) -> bool {
+        self.layers.iter().any(|layer| path.starts_with(layer))
+    }
+}
+
+/// Walk a package directory. Paths must be absolute.
+fn layer_entries(layer: &Path) -> io::Result<Vec<PackageEntry>> {
+    let mut entries = vec![];
+    // By default directories are yielded before their contents.
+    for entry in WalkDir::new(layer) {
+        let entry = entry?;
+        let rel_path = diff_paths(entry.path(), layer)
+            .expect("Can't calculate relative path; arguments are not both absolute paths");
+        let file_meta = entry.metadata()?;
+        if !file_meta.is_file() && !file_meta.is_dir() {
+            // To simplify
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Package must contain only files and directories; symlink is not supported.",
+            ));
+        }
+        entries.push(PackageEntry {
+            rel_path,
+            source: entry.into_path(),
+            is_dir: file_meta.is_dir(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Names of the packages in the repo, which are all subdirectories
+/// not starting with a dot, sorted. Variants of packages are not
+/// packages by themselves.
+fn list_packages(repo: &Path) -> io::Result<Vec<String>> {
+    let mut packages = vec![];
+    for entry in fs::read_dir(repo)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            if !name.starts_with('.') && !name.contains('@') {
+                packages.push(name.to_string());
+            }
+        }
+    }
+    packages.sort();
+    Ok(packages)
 }
 
 /// For every regular file in the package, check whether there is a
 /// symlink pointing to it correctly. Directories must exist with the
 /// same structure.
-/// Paths must be both absolute.
-fn check_linked(repo: &Path, linked: &Path, package: &str) -> io::Result<bool> {
-    let pkg_dir = repo.join(package);
-    for entry in WalkDir::new(&pkg_dir) {
-        let entry = entry?;
-        let pkg_path = entry.path();
-        // Relative path. Should be the same for the file relative
-        // to the package directory and the link relative to the
-        // installation directory.
-        let rel_path = diff_paths(pkg_path, &pkg_dir)
-            .expect("Can't calculate relative path; arguments are not both absolute paths");
-        let link_path = linked.join(&rel_path);
+fn check_linked(package: &Package) -> io::Result<bool> {
+    for entry in package.entries()? {
+        let link_path = package.install.join(&entry.rel_path);
         if !link_path.exists() {
             return Ok(false);
         }
-        let file_meta = entry.metadata()?;
-        if file_meta.is_file() {
-            let is_link = link_path.symlink_metadata()?.file_type().is_symlink();
-            let is_correct = is_link && is_same_file(pkg_path, link_path)?;
-            if !is_correct {
-                return Ok(false);
-            }
-        } else if file_meta.is_dir() {
+        if entry.is_dir {
             if !link_path.is_dir() {
                 return Ok(false);
             }
         } else {
-            // To simplify
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Package must contain only files and directories; symlink is not supported.",
-            ));
+            let is_link = link_path.symlink_metadata()?.file_type().is_symlink();
+            let is_correct = is_link && is_same_file(&entry.source, link_path)?;
+            if !is_correct {
+                return Ok(false);
+            }
         }
     }
     Ok(true)
@@ -174,135 +577,260 @@ fn check_linked(repo: &Path, linked: &Path, package: &str) -> io::Result<bool> {
 /// For every regular file in the package, make sure there are no
 /// symlinks pointing to it. Directories can exist within the
 /// installation path because they may be shared.
-/// Paths must be both absolute.
-fn check_unlinked(repo: &Path, install: &Path, package: &str) -> io::Result<bool> {
-    let pkg_dir = repo.join(package);
-    for entry in WalkDir::new(&pkg_dir) {
-        let entry = entry?;
-        let pkg_path = entry.path();
-        // Relative path. Should be the same for the file relative
-        // to the package directory and the link relative to the
-        // installation directory.
-        let rel_path = diff_paths(pkg_path, &pkg_dir)
-            .expect("Can't calculate relative path; arguments are not both absolute paths");
-        let link_path = install.join(&rel_path);
+fn check_unlinked(package: &Package) -> io::Result<bool> {
+    for entry in package.all_entries()? {
+        let link_path = package.install.join(&entry.rel_path);
         if !link_path.exists() {
             continue;
         }
-        let file_meta = entry.metadata()?;
-        if file_meta.is_file() {
-            if is_same_file(pkg_path, link_path)? {
-                return Ok(false);
-            }
-        } else if !file_meta.is_dir() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Package must contain only files and directories; symlink is not supported.",
-            ));
+        if !entry.is_dir && is_same_file(&entry.source, link_path)? {
+            return Ok(false);
         }
     }
     Ok(true)
 }
 
-fn link_package(repo: &Path, install: &Path, package: &str) -> io::Result<()> {
-    let pkg_dir = repo.join(package);
-    // By default directories are yielded before their contents.
-    for entry in WalkDir::new(&pkg_dir) {
-        let entry = entry?;
-        let pkg_path = entry.path();
-        // Relative path to bases.
-        let rel_path = diff_paths(pkg_path, &pkg_dir)
-            .expect("Can't calculate relative path; arguments are not both absolute paths");
-        let link_path = install.join(&rel_path);
-
-        let file_meta = entry.metadata()?;
-        if file_meta.is_file() {
-            match link_path.symlink_metadata() {
-                Ok(meta) => {
-                    if meta.file_type().is_symlink() {
-                        if link_path.exists() {
-                            // Non-broken symlink
-                            if is_same_file(&link_path, &pkg_path)? {
-                                continue;
-                            }
-                        // It's okay if it doesn't point to the file in this package.
-                        // The symlink may belong to another package.
-                        } else {
-                            return Err(io::Error::new(
-                                io::ErrorKind::AlreadyExists,
-                                format!("{:?} is a broken symlink", link_path),
-                            ));
+fn link_package(package: &Package) -> io::Result<()> {
+    for entry in package.entries()? {
+        let link_path = package.install.join(&entry.rel_path);
+        if entry.is_dir {
+            if !link_path.is_dir() {
+                println!(
+                    "Creating directory {:?} in package {}",
+                    entry.rel_path, package.name
+                );
+                fs::create_dir(&link_path)?;
+            }
+            continue;
+        }
+        match link_path.symlink_metadata() {
+            Ok(meta) => {
+                if meta.file_type().is_symlink() {
+                    if link_path.exists() {
+                        // Non-broken symlink
+                        if is_same_file(&link_path, &entry.source)? {
+                            continue;
                         }
+                        // A link to a file overridden by a variant, or the other
+                        // way around, is replaced.
+                        if package.provides(&link_target(&link_path)?) {
+                            println!("Replacing symlink {:?}", link_path);
+                            fs::remove_file(&link_path)?;
+                            symlink_relative(&entry.source, &link_path)?;
+                        }
+                    // It's okay if it doesn't point to the file in this package.
+                    // The symlink may belong to another package.
                     } else {
                         return Err(io::Error::new(
                             io::ErrorKind::AlreadyExists,
-                            format!(
-                                "{:?} exists, can't create symlink for {}",
-                                link_path, package
-                            ),
+                            format!("{:?} is a broken symlink", link_path),
                         ));
                     }
+                } else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!(
+                            "{:?} exists, can't create symlink for {}",
+                            link_path, package.name
+                        ),
+                    ));
                 }
-                Err(e) => {
-                    if e.kind() == io::ErrorKind::NotFound {
-                        let link_dir = link_path
-                            .parent()
-                            .expect("No directory where symlink should be created");
-                        let rel_link = diff_paths(pkg_path, &link_dir)
-                            .expect("Can't calculate relative path for symlink creation");
-                        symlink_file(rel_link, link_path)?;
-                    } else {
-                        return Err(e);
-                    }
+            }
+            Err(e) => {
+                if e.kind() == io::ErrorKind::NotFound {
+                    symlink_relative(&entry.source, &link_path)?;
+                } else {
+                    return Err(e);
                 }
             }
-        } else if file_meta.is_dir() {
-            if !link_path.is_dir() {
-                println!("Creating directory {:?} in package {}", rel_path, package);
-                fs::create_dir(&link_path)?;
+        }
+    }
+    Ok(())
+}
+
+fn unlink_package(package: &Package) -> io::Result<()> {
+    for entry in package.all_entries()? {
+        if entry.is_dir {
+            continue;
+        }
+        let link_path = package.install.join(&entry.rel_path);
+        match link_path.symlink_metadata() {
+            Ok(meta) => {
+                if meta.file_type().is_symlink() && is_same_file(&link_path, &entry.source)? {
+                    // Remove link only when it points to the corresponding file in the package
+                    fs::remove_file(&link_path)?;
+                }
+            }
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(e);
+                }
             }
-        } else {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Package must contain only files and directories; symlink is not supported.",
-            ));
         }
     }
     Ok(())
 }
 
-fn unlink_package(repo: &Path, install: &Path, package: &str) -> io::Result<()> {
-    let pkg_dir = repo.join(package);
-    for entry in WalkDir::new(&pkg_dir) {
-        let entry = entry?;
-        let pkg_path = entry.path();
-        let rel_path = diff_paths(pkg_path, &pkg_dir)
-            .expect("Can't calculate relative path; arguments are not both absolute paths");
-        let link_path = install.join(&rel_path);
+/// Create a symlink at link_path pointing to the file using a
+/// path relative to the directory of the link
+fn symlink_relative(file: &Path, link_path: &Path) -> io::Result<()> {
+    let link_dir = link_path
+        .parent()
+        .expect("No directory where symlink should be created");
+    let rel_link =
+        diff_paths(file, link_dir).expect("Can't calculate relative path for symlink creation");
+    symlink_file(rel_link, link_path)
+}
 
-        let file_meta = entry.metadata()?;
-        if file_meta.is_file() {
-            match link_path.symlink_metadata() {
-                Ok(meta) => {
-                    if meta.file_type().is_symlink() && is_same_file(&link_path, &pkg_path)? {
-                        // Remove link only when it points to the corresponding file in the package
-                        fs::remove_file(&link_path)?;
-                    }
+/// Whether pruning the installation directory of the package would
+/// remove anything
+fn check_pruned(repo: &Path, package: &Package, unlink: bool) -> io::Result<bool> {
+    let pruned = prune_dir(&package.install, repo, package, unlink, true)?;
+    Ok(pruned.removed == 0)
+}
+
+/// Remove broken symlinks pointing into the repo from the installation
+/// directory, along with links into the package when `unlink` is set.
+/// Directories which held only such links are removed once empty, so
+/// empty directories created by the user are kept. The installation
+/// directory itself is never removed.
+fn prune_package(repo: &Path, package: &Package, unlink: bool) -> io::Result<()> {
+    prune_dir(&package.install, repo, package, unlink, false)?;
+    Ok(())
+}
+
+/// What pruning a directory removed, or would remove
+struct Pruned {
+    /// Links and directories removed anywhere below the directory
+    removed: usize,
+    /// Everything in the directory was removed
+    emptied: bool,
+}
+
+/// Prune a directory recursively, deepest first, without changing
+/// anything in a dry run. At the top of the installation directory
+/// only directories which some package in the repo also has at its
+/// top are searched, so a home directory isn't walked entirely.
+fn prune_dir(
+    dir: &Path,
+    repo: &Path,
+    package: &Package,
+    unlink: bool,
+    dry_run: bool,
+) -> io::Result<Pruned> {
+    let mut pruned = Pruned {
+        removed: 0,
+        emptied: false,
+    };
+    if !dir.is_dir() {
+        return Ok(pruned);
+    }
+    let top = dir == package.install;
+    let mut entries = 0;
+    let mut removed_here = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        entries += 1;
+        let meta = path.symlink_metadata()?;
+        if meta.file_type().is_symlink() {
+            let target = link_target(&path)?;
+            let broken = !path.exists() && target.starts_with(repo);
+            if broken || (unlink && package.provides(&target)) {
+                if !dry_run {
+                    println!("Removing symlink {:?}", path);
+                    fs::remove_file(&path)?;
                 }
-                Err(e) => {
-                    if e.kind() != io::ErrorKind::NotFound {
-                        return Err(e);
-                    }
+                pruned.removed += 1;
+                removed_here += 1;
+            }
+        } else if meta.is_dir() && (!top || in_repo_top(repo, path.file_name())?) {
+            let sub = prune_dir(&path, repo, package, unlink, dry_run)?;
+            pruned.removed += sub.removed;
+            if sub.emptied {
+                if !dry_run {
+                    println!("Removing empty directory {:?}", path);
+                    fs::remove_dir(&path)?;
                 }
+                pruned.removed += 1;
+                removed_here += 1;
             }
-        } else if !file_meta.is_dir() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Package must contain only files and directories.",
-            ));
         }
     }
-    Ok(())
+    pruned.emptied = removed_here > 0 && removed_here == entries;
+    Ok(pruned)
+}
+
+/// Whether a package or variant in the repo has a top-level directory
+/// with this name
+fn in_repo_top(repo: &Path, name: Option<&std::ffi::OsStr>) -> io::Result<bool> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(false),
+    };
+    for entry in fs::read_dir(repo)? {
+        if entry?.path().join(name).is_dir() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Symlinks in the installation directory that point into the package
+/// but whose targets don't exist any more. Entries directly in the
+/// installation directory are checked, and directories there which
+/// also exist in the package are searched recursively.
+fn stale_links(package: &Package) -> io::Result<Vec<PathBuf>> {
+    let mut stale = vec![];
+    if !package.install.is_dir() {
+        return Ok(stale);
+    }
+    let walker = WalkDir::new(&package.install)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() > 1
+                || !e.file_type().is_dir()
+                || package
+                    .layers
+                    .iter()
+                    .any(|layer| layer.join(e.file_name()).is_dir())
+        });
+    for entry in walker {
+        let entry = entry?;
+        if entry.path_is_symlink() && !entry.path().exists() {
+            let target = link_target(entry.path())?;
+            if package.provides(&target) {
+                stale.push(entry.into_path());
+            }
+        }
+    }
+    Ok(stale)
+}
+
+/// Absolute path a symlink points to, without requiring the target
+/// to exist.
+fn link_target(link: &Path) -> io::Result<PathBuf> {
+    let target = fs::read_link(link)?;
+    let link_dir = link
+        .parent()
+        .expect("A symlink must be in a directory")
+        .canonicalize()?;
+    Ok(normalize(&link_dir.join(target)))
+}
+
+/// Resolve `.` and `..` components lexically.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c.as_os_str()),
+        }
+    }
+    normalized
 }
 
 #[cfg(unix)]
@@ -331,8 +859,223 @@ impl fmt::Display for PackageLinked {
             "Packages {:?} are linked from {:?} ",
             self.linked, self.repo
         )?;
+        if self.link_rest {
+            write!(f, "along with all other packages ")?;
+        }
         write!(f, "to {}; ", install_dir)?;
+        for (package, target) in &self.targets {
+            write!(f, "package {} to {:?}; ", package, target)?;
+        }
+        if !self.variants.is_empty() {
+            write!(f, "variants {:?} are overlaid; ", self.variants)?;
+        }
         write!(f, "packages {:?} are not linked", self.unlinked)?;
+        if self.prune {
+            write!(f, "; broken links are pruned")?;
+        }
+        if self.restow {
+            write!(f, "; stale links are removed")?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+
+    /// Create an empty directory for a test, with a repo containing
+    /// a package and an installation directory
+    fn setup(name: &str) -> (PathBuf, PathBuf) {
+        let base = temp_dir().join(name);
+        if base.exists() {
+            fs::remove_dir_all(&base).unwrap();
+        }
+        let repo = base.join("repo");
+        let install = base.join("install");
+        fs::create_dir_all(repo.join("pkg/.config/app")).unwrap();
+        fs::create_dir_all(&install).unwrap();
+        fs::write(repo.join("pkg/.config/app/conf"), b"a").unwrap();
+        (repo.canonicalize().unwrap(), install)
+    }
+
+    fn package(repo: &Path, install: &Path, name: &str) -> Package {
+        PackageLinked::new(Arc::new(repo.to_path_buf().into()))
+            .install_to(install)
+            .package(repo, name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_prune_renamed() {
+        let (repo, install) = setup("package_linked_test_prune_renamed");
+        let pkg = package(&repo, &install, "pkg");
+        link_package(&pkg).unwrap();
+        assert!(check_linked(&pkg).unwrap());
+        assert!(check_pruned(&repo, &pkg, false).unwrap());
+
+        let app = repo.join("pkg/.config/app");
+        fs::rename(app.join("conf"), app.join("conf.new")).unwrap();
+        assert!(!check_linked(&pkg).unwrap());
+        link_package(&pkg).unwrap();
+        assert!(check_linked(&pkg).unwrap());
+        // The link to the old name is left behind
+        assert!(!check_pruned(&repo, &pkg, false).unwrap());
+
+        prune_package(&repo, &pkg, false).unwrap();
+        assert!(check_pruned(&repo, &pkg, false).unwrap());
+        assert!(check_linked(&pkg).unwrap());
+        assert!(install.join(".config/app/conf").symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn test_prune_unlinked() {
+        let (repo, install) = setup("package_linked_test_prune_unlinked");
+        let pkg = package(&repo, &install, "pkg");
+        fs::create_dir_all(install.join(".config/other")).unwrap();
+        link_package(&pkg).unwrap();
+        assert!(!check_pruned(&repo, &pkg, true).unwrap());
+
+        prune_package(&repo, &pkg, true).unwrap();
+        assert!(check_unlinked(&pkg).unwrap());
+        assert!(check_pruned(&repo, &pkg, true).unwrap());
+        assert!(!install.join(".config/app").exists());
+        // Directories that didn't only hold links are kept, even if
+        // they are empty
+        assert!(install.join(".config/other").is_dir());
+        assert!(install.is_dir());
+    }
+
+    #[test]
+    fn test_prune_renamed_dir() {
+        let (repo, install) = setup("package_linked_test_prune_renamed_dir");
+        let pkg = package(&repo, &install, "pkg");
+        link_package(&pkg).unwrap();
+        fs::write(install.join(".config/user.conf"), b"").unwrap();
+        let config = repo.join("pkg/.config");
+        fs::rename(config.join("app"), config.join("app2")).unwrap();
+        assert!(!check_pruned(&repo, &pkg, false).unwrap());
+
+        prune_package(&repo, &pkg, false).unwrap();
+        assert!(check_pruned(&repo, &pkg, false).unwrap());
+        assert!(!install.join(".config/app").exists());
+        assert!(install.join(".config").is_dir());
+    }
+
+    #[test]
+    fn test_stale_links() {
+        let (repo, install) = setup("package_linked_test_stale_links");
+        let pkg = package(&repo, &install, "pkg");
+        link_package(&pkg).unwrap();
+        assert!(stale_links(&pkg).unwrap().is_empty());
+
+        // The directory containing the link is not in the package any more
+        let config = repo.join("pkg/.config");
+        fs::rename(config.join("app"), config.join("app2")).unwrap();
+        let stale = stale_links(&pkg).unwrap();
+        assert_eq!(stale, vec![install.join(".config/app/conf")]);
+        // Links into other packages are left alone
+        assert!(stale_links(&package(&repo, &install, "other"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_linked_rest() {
+        let (repo, _install) = setup("package_linked_test_linked_rest");
+        for dir in &["a", "b", ".git"] {
+            fs::create_dir(repo.join(dir)).unwrap();
+        }
+        fs::write(repo.join("README"), b"").unwrap();
+        assert_eq!(list_packages(&repo).unwrap(), vec!["a", "b", "pkg"]);
+
+        let linked = PackageLinked::new(Arc::new(repo.clone().into()))
+            .linked("pkg")
+            .unlinked("a");
+        assert_eq!(linked.unlisted_packages(&repo).unwrap(), vec!["b"]);
+        assert_eq!(linked.linked_packages(&repo).unwrap(), vec!["pkg"]);
+        let linked = linked.linked_rest();
+        assert_eq!(linked.linked_packages(&repo).unwrap(), vec!["pkg", "b"]);
+    }
+
+    #[test]
+    fn test_variants_and_targets() {
+        let (repo, install) = setup("package_linked_test_variants");
+        let etc = install.join("etc");
+        fs::create_dir_all(repo.join("pkg@laptop/.config/app")).unwrap();
+        fs::write(repo.join("pkg@laptop/.config/app/conf"), b"b").unwrap();
+        fs::write(repo.join("pkg@laptop/.config/app/extra"), b"c").unwrap();
+        fs::create_dir_all(repo.join("sys")).unwrap();
+        fs::write(repo.join("sys/sys.conf"), b"d").unwrap();
+        fs::create_dir(&etc).unwrap();
+        assert_eq!(list_packages(&repo).unwrap(), vec!["pkg", "sys"]);
+
+        let linked = PackageLinked::new(Arc::new(repo.clone().into()))
+            .install_to(&install)
+            .install_package_to("sys", &etc)
+            .linked_all(&["pkg", "sys"]);
+        // Without the variant, the file in the package is linked
+        let property: Box<dyn Property<Any>> = Box::new(linked.clone());
+        property.apply(&Facts::default()).unwrap();
+        assert!(property.check(&Facts::default()).unwrap());
+        assert_eq!(fs::read(install.join(".config/app/conf")).unwrap(), b"a");
+        assert_eq!(fs::read(etc.join("sys.conf")).unwrap(), b"d");
+        assert!(!install.join("sys.conf").exists());
+
+        let property: Box<dyn Property<Any>> = Box::new(linked.variant("laptop"));
+        assert!(!property.check(&Facts::default()).unwrap());
+        property.apply(&Facts::default()).unwrap();
+        assert!(property.check(&Facts::default()).unwrap());
+        assert_eq!(fs::read(install.join(".config/app/conf")).unwrap(), b"b");
+        assert_eq!(fs::read(install.join(".config/app/extra")).unwrap(), b"c");
+
+        let pkg = package(&repo, &install, "pkg");
+        unlink_package(&pkg).unwrap();
+        assert!(check_unlinked(&pkg).unwrap());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let (repo, install) = setup("package_linked_test_conflicts");
+        fs::create_dir_all(repo.join("other/.config/app")).unwrap();
+        fs::write(repo.join("other/.config/app/conf"), b"b").unwrap();
+        fs::write(repo.join("other/.profile"), b"c").unwrap();
+        fs::write(install.join(".profile"), b"d").unwrap();
+
+        let linked = PackageLinked::new(Arc::new(repo.clone().into()))
+            .install_to(&install)
+            .linked_all(&["pkg", "other"]);
+        let packages = linked.packages(&repo, &["pkg", "other"]).unwrap();
+        let conflicts = linked.plan(&repo, &packages, &[]).unwrap();
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict::Duplicate {
+                    path: install.join(".config/app/conf"),
+                    packages: ("pkg".to_string(), "other".to_string()),
+                },
+                Conflict::Occupied {
+                    path: install.join(".profile"),
+                    package: "other".to_string(),
+                },
+            ]
+        );
+        // Nothing is linked when there are conflicts
+        assert!((&linked as &dyn Property<Any>)
+            .apply(&Facts::default())
+            .is_err());
+        assert!(!install.join(".config").exists());
+
+        // Links to packages being unlinked are not in the way
+        let pkg = package(&repo, &install, "pkg");
+        link_package(&pkg).unwrap();
+        let linked = PackageLinked::new(Arc::new(repo.clone().into()))
+            .install_to(&install)
+            .linked("other")
+            .unlinked("pkg");
+        let packages = linked.packages(&repo, &["other"]).unwrap();
+        let conflicts = linked.plan(&repo, &packages, &[pkg]).unwrap();
+        assert_eq!(conflicts.len(), 1);
+    }
+}
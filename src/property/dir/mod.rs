@@ -4,9 +4,13 @@ use crate::property::dir::internal::ManagedDir;
 use crate::util::UserPathBuf;
 use std::sync::Arc;
 
+mod existence;
 mod internal;
+mod sync;
 
+use self::existence::{DirAbsent, DirExists, DirPurged};
 use self::internal::PackageLinked;
+use self::sync::DirSynced;
 
 #[allow(dead_code)]
 pub fn path<P: Into<UserPathBuf>>(path: P) -> ManagedDir {
@@ -25,4 +29,33 @@ impl ManagedDir {
     pub fn as_package_source(&self) -> PackageLinked {
         PackageLinked::new(self.path.clone())
     }
+
+    /// The directory exists, creating it and its parents if needed
+    #[allow(dead_code)]
+    pub fn exists(&self) -> DirExists {
+        DirExists::new(self.path.clone())
+    }
+
+    /// The directory is removed with all its contents.
+    /// The root and home directories are never removed.
+    #[allow(dead_code)]
+    pub fn absent(&self) -> DirAbsent {
+        DirAbsent::new(self.path.clone())
+    }
+
+    /// Entries in the directory other than the given ones are
+    /// removed, including subdirectories with their contents
+    #[allow(dead_code)]
+    pub fn purged_except(&self, kept: &[&'static str]) -> DirPurged {
+        DirPurged::new(self.path.clone(), kept)
+    }
+
+    /// The directory contains copies of the files in the source
+    /// directory, for programs that don't accept symlinks.
+    /// Content and permissions are compared; extra files are kept
+    /// unless deleted explicitly.
+    #[allow(dead_code)]
+    pub fn synced_from<P: Into<UserPathBuf>>(&self, source: P) -> DirSynced {
+        DirSynced::new(self.path.clone(), source.into())
+    }
 }
@@ -0,0 +1,369 @@
+//! Copy a directory tree, similar to rsync
+
+use crate::os::Any;
+use crate::property::Property;
+use crate::util::UserPathBuf;
+use crate::Facts;
+use crate::PrResult;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use pathdiff::diff_paths;
+use walkdir::WalkDir;
+
+const BUF_SIZE: usize = 8 * 1024;
+
+/// The directory contains copies of the files in the source directory
+#[derive(Clone)]
+pub struct DirSynced {
+    pub path: Arc<UserPathBuf>,
+    source: UserPathBuf,
+    /// Remove files and directories not in the source
+    delete: bool,
+    /// Compare sizes and modification times instead of content
+    by_mtime: bool,
+}
+
+/// How a file or directory in the target differs from the source
+#[derive(Debug, PartialEq)]
+enum Difference {
+    Missing(PathBuf),
+    /// A file where there should be a directory, or the other way around
+    Kind(PathBuf),
+    Content(PathBuf),
+    Mode(PathBuf),
+    /// Not in the source
+    Extra(PathBuf),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Difference::Missing(p) => write!(f, "{:?} is missing", p),
+            Difference::Kind(p) => write!(f, "{:?} has a different type", p),
+            Difference::Content(p) => write!(f, "{:?} has different content", p),
+            Difference::Mode(p) => write!(f, "{:?} has a different mode", p),
+            Difference::Extra(p) => write!(f, "{:?} is not in the source", p),
+        }
+    }
+}
+
+impl DirSynced {
+    pub fn new(path: Arc<UserPathBuf>, source: UserPathBuf) -> DirSynced {
+        DirSynced {
+            path,
+            source,
+            delete: false,
+            by_mtime: false,
+        }
+    }
+
+    /// Also remove files and directories that are not in the source
+    #[allow(dead_code)]
+    pub fn deleting_extra(mut self) -> DirSynced {
+        self.delete = true;
+        self
+    }
+
+    /// Consider files with the same size and modification time
+    /// to be the same instead of comparing their content.
+    /// Modification times are copied along with the content.
+    #[allow(dead_code)]
+    pub fn by_mtime(mut self) -> DirSynced {
+        self.by_mtime = true;
+        self
+    }
+
+    /// Relative paths of everything that differs, with parents
+    /// before their contents
+    fn differences(&self, source: &Path, target: &Path) -> io::Result<Vec<Difference>> {
+        let mut differences = vec![];
+        // Directories that are missing or replaced; their contents
+        // don't need to be compared
+        let mut replaced: Vec<PathBuf> = vec![];
+        for entry in WalkDir::new(source)
+            .follow_links(true)
+            .min_depth(1)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        {
+            let entry = entry?;
+            let rel_path = diff_paths(entry.path(), source)
+                .expect("Can't calculate relative path; arguments are not both absolute paths");
+            if replaced.iter().any(|dir| rel_path.starts_with(dir)) {
+                continue;
+            }
+            let src_meta = entry.metadata()?;
+            let dst = target.join(&rel_path);
+            let dst_meta = match dst.symlink_metadata() {
+                Ok(meta) => meta,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                    if src_meta.is_dir() {
+                        replaced.push(rel_path.clone());
+                    }
+                    differences.push(Difference::Missing(rel_path));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if src_meta.is_dir() != dst_meta.is_dir() || dst_meta.file_type().is_symlink() {
+                if src_meta.is_dir() {
+                    replaced.push(rel_path.clone());
+                }
+                differences.push(Difference::Kind(rel_path));
+                continue;
+            }
+            if src_meta.is_file() && !self.same_content(entry.path(), &dst, &src_meta, &dst_meta)? {
+                differences.push(Difference::Content(rel_path));
+            } else if !same_mode(&src_meta, &dst_meta) {
+                differences.push(Difference::Mode(rel_path));
+            }
+        }
+        if self.delete && target.is_dir() {
+            for entry in WalkDir::new(target)
+                .min_depth(1)
+                .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            {
+                let entry = entry?;
+                let rel_path = diff_paths(entry.path(), target)
+                    .expect("Can't calculate relative path; arguments are not both absolute paths");
+                let is_extra = match source.join(&rel_path).metadata() {
+                    Ok(_) => false,
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => true,
+                    Err(e) => return Err(e),
+                };
+                let in_extra = differences.iter().any(|d| match d {
+                    Difference::Extra(dir) => rel_path.starts_with(dir),
+                    _ => false,
+                });
+                if is_extra && !in_extra {
+                    differences.push(Difference::Extra(rel_path));
+                }
+            }
+        }
+        Ok(differences)
+    }
+
+    fn same_content(
+        &self,
+        src: &Path,
+        dst: &Path,
+        src_meta: &fs::Metadata,
+        dst_meta: &fs::Metadata,
+    ) -> io::Result<bool> {
+        if src_meta.len() != dst_meta.len() {
+            return Ok(false);
+        }
+        if self.by_mtime {
+            return Ok(src_meta.modified()? == dst_meta.modified()?);
+        }
+        same_bytes(fs::File::open(src)?, fs::File::open(dst)?)
+    }
+
+    fn get_paths(&self) -> io::Result<(PathBuf, PathBuf)> {
+        Ok((self.source.expand_user()?, self.path.expand_user()?))
+    }
+}
+
+impl fmt::Display for DirSynced {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Directory {:?} is synced from {:?}",
+            self.path, self.source
+        )?;
+        if self.delete {
+            write!(f, ", without extra files")?;
+        }
+        Ok(())
+    }
+}
+
+impl Property<Any> for DirSynced {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let (source, target) = self.get_paths()?;
+        if !target.is_dir() {
+            return Ok(false);
+        }
+        let differences = self.differences(&source, &target)?;
+        for difference in &differences {
+            println!("{}", difference);
+        }
+        Ok(differences.is_empty())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let (source, target) = self.get_paths()?;
+        if !source.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Source directory {:?} not found", source),
+            ));
+        }
+        fs::create_dir_all(&target)?;
+        for difference in self.differences(&source, &target)? {
+            match difference {
+                Difference::Missing(rel_path) => {
+                    self.copy(&source.join(&rel_path), &target.join(&rel_path))?;
+                }
+                Difference::Kind(rel_path) => {
+                    let dst = target.join(&rel_path);
+                    remove(&dst)?;
+                    self.copy(&source.join(&rel_path), &dst)?;
+                }
+                Difference::Content(rel_path) => {
+                    self.copy(&source.join(&rel_path), &target.join(&rel_path))?;
+                }
+                Difference::Mode(rel_path) => {
+                    let permissions = source.join(&rel_path).metadata()?.permissions();
+                    fs::set_permissions(target.join(&rel_path), permissions)?;
+                }
+                Difference::Extra(rel_path) => {
+                    println!("Removing {:?}", rel_path);
+                    remove(&target.join(&rel_path))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
+}
+
+impl DirSynced {
+    /// Copy a file or a whole directory, with permissions
+    fn copy(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        println!("Copying {:?} to {:?}", src, dst);
+        for entry in WalkDir::new(src).follow_links(true) {
+            let entry = entry?;
+            let rel_path = diff_paths(entry.path(), src)
+                .expect("Can't calculate relative path; arguments are not both absolute paths");
+            // Joining an empty path would add a trailing slash
+            let dst = if entry.depth() == 0 {
+                dst.to_path_buf()
+            } else {
+                dst.join(&rel_path)
+            };
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                if !dst.is_dir() {
+                    fs::create_dir(&dst)?;
+                }
+                fs::set_permissions(&dst, meta.permissions())?;
+            } else {
+                // Permissions are copied too
+                fs::copy(entry.path(), &dst)?;
+                if self.by_mtime {
+                    fs::File::options()
+                        .write(true)
+                        .open(&dst)?
+                        .set_modified(meta.modified()?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove a file, symlink or directory with its contents
+fn remove(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(unix)]
+fn same_mode(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    a.permissions().mode() == b.permissions().mode()
+}
+
+#[cfg(windows)]
+fn same_mode(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    a.permissions().readonly() == b.permissions().readonly()
+}
+
+/// Compare everything read from two sources
+fn same_bytes<A: Read, B: Read>(mut a: A, mut b: B) -> io::Result<bool> {
+    let mut buf_a = [0u8; BUF_SIZE];
+    let mut buf_b = [0u8; BUF_SIZE];
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            // The other one must be exhausted too
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        if b.read_exact(&mut buf_b[..n]).is_err() || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property::dir::path;
+    use std::env::temp_dir;
+
+    #[test]
+    fn test_dir_synced() {
+        let base = temp_dir().join("dir_synced_test");
+        if base.exists() {
+            fs::remove_dir_all(&base).unwrap();
+        }
+        let source = base.join("source");
+        let target = base.join("target");
+        fs::create_dir_all(source.join("sub/deeper")).unwrap();
+        fs::write(source.join("a"), b"a").unwrap();
+        fs::write(source.join("sub/deeper/b"), b"b").unwrap();
+
+        let synced = path(&target).synced_from(&source);
+        let prop: Box<dyn Property<Any>> = Box::new(synced.clone());
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
+        assert_eq!(fs::read(target.join("sub/deeper/b")).unwrap(), b"b");
+
+        fs::write(target.join("a"), b"c").unwrap();
+        fs::remove_dir_all(target.join("sub")).unwrap();
+        fs::write(target.join("sub"), b"").unwrap();
+        fs::create_dir_all(target.join("extra/dir")).unwrap();
+        let differences = synced.differences(&source, &target).unwrap();
+        assert_eq!(
+            differences,
+            vec![
+                Difference::Content("a".into()),
+                Difference::Kind("sub".into())
+            ]
+        );
+        // Extra files are kept unless they should be deleted
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
+        assert!(target.join("extra").is_dir());
+
+        let synced = synced.deleting_extra();
+        let differences = synced.differences(&source, &target).unwrap();
+        assert_eq!(differences, vec![Difference::Extra("extra".into())]);
+        let prop: Box<dyn Property<Any>> = Box::new(synced);
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
+        assert!(!target.join("extra").exists());
+    }
+
+    #[test]
+    fn test_same_bytes() {
+        assert!(same_bytes(&b"abc"[..], &b"abc"[..]).unwrap());
+        assert!(!same_bytes(&b"abc"[..], &b"abd"[..]).unwrap());
+        assert!(!same_bytes(&b"abc"[..], &b"ab"[..]).unwrap());
+        assert!(!same_bytes(&b"ab"[..], &b"abc"[..]).unwrap());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::os;
+use crate::property::Property;
+use crate::Facts;
+use crate::PrResult;
+use std::fmt;
+use std::io;
+use std::process;
+
+/// packages are installed by dnf
+#[derive(Clone)]
+pub struct DnfInstalled {
+    pub packages: Vec<&'static str>,
+}
+
+/// packages are not installed
+#[derive(Clone)]
+pub struct DnfRemoved {
+    pub packages: Vec<&'static str>,
+}
+
+/// Packages which rpm reports as not installed, in the given order.
+/// Names may also be capabilities provided by other packages, such
+/// as `/usr/bin/vim` or `python3dist(requests)`.
+fn not_installed<'a>(packages: &[&'a str]) -> io::Result<Vec<&'a str>> {
+    // Exits with the number of packages not installed
+    let out = process::Command::new("rpm")
+        .arg("-q")
+        .arg("--whatprovides")
+        .args(packages)
+        .env("LC_ALL", "C")
+        .stdin(process::Stdio::null())
+        .output()?;
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let missing = parse_not_installed(&stdout, packages);
+    // Warnings on stderr don't make the query fail
+    if !out.status.success() && missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "rpm -q {:?} failed: {}",
+                packages,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        ));
+    }
+    Ok(missing)
+}
+
+/// Find lines such as `no package provides vim`
+fn parse_not_installed<'a>(out: &str, packages: &[&'a str]) -> Vec<&'a str> {
+    let missing: Vec<&str> = out
+        .lines()
+        .filter_map(|l| l.strip_prefix("no package provides "))
+        .collect();
+    packages
+        .iter()
+        .filter(|p| missing.contains(p))
+        .cloned()
+        .collect()
+}
+
+fn run_dnf(command: &str, packages: &[&str]) -> io::Result<()> {
+    let s = process::Command::new("dnf")
+        .arg("--assumeyes")
+        .arg(command)
+        .args(packages)
+        .status()?;
+    if !s.success() {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("dnf {} {:?} failed", command, packages),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+impl fmt::Display for DnfInstalled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.packages.len() == 1 {
+            write!(f, "package {} is installed by dnf", self.packages[0])
+        } else {
+            write!(f, "packages {:?} are installed by dnf", self.packages)
+        }
+    }
+}
+
+impl fmt::Display for DnfRemoved {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.packages.len() == 1 {
+            write!(f, "package {} is not installed", self.packages[0])
+        } else {
+            write!(f, "packages {:?} are not installed", self.packages)
+        }
+    }
+}
+
+impl Property<os::RedHatLike> for DnfInstalled {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        Ok(not_installed(&self.packages)?.is_empty())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        run_dnf("install", &not_installed(&self.packages)?)
+    }
+}
+
+impl Property<os::RedHatLike> for DnfRemoved {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        Ok(not_installed(&self.packages)?.len() == self.packages.len())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let missing = not_installed(&self.packages)?;
+        let installed: Vec<&str> = self
+            .packages
+            .iter()
+            .filter(|p| !missing.contains(p))
+            .cloned()
+            .collect();
+        run_dnf("remove", &installed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_not_installed() {
+        let out = "\
+vim-enhanced-9.0.2081-1.fc39.x86_64
+no package provides tmux
+bash-5.2.26-1.fc39.x86_64
+no package provides htop
+";
+        let packages = ["/usr/bin/vim", "htop", "tmux", "bash"];
+        assert_eq!(parse_not_installed(out, &packages), vec!["htop", "tmux"]);
+    }
+}
@@ -0,0 +1,32 @@
+//! Manage packages on Fedora, RHEL and derivatives
+//! dnf and rpm may be invoked
+
+mod internal;
+
+use self::internal::{DnfInstalled, DnfRemoved};
+
+/// package is installed by dnf
+#[allow(dead_code)]
+pub fn installed(package: &'static str) -> DnfInstalled {
+    installed_all(&[package])
+}
+
+/// packages are installed by dnf
+#[allow(dead_code)]
+pub fn installed_all(packages: &[&'static str]) -> DnfInstalled {
+    let packages = packages.to_vec();
+    DnfInstalled { packages }
+}
+
+/// package is not installed
+#[allow(dead_code)]
+pub fn removed(package: &'static str) -> DnfRemoved {
+    removed_all(&[package])
+}
+
+/// packages are not installed
+#[allow(dead_code)]
+pub fn removed_all(packages: &[&'static str]) -> DnfRemoved {
+    let packages = packages.to_vec();
+    DnfRemoved { packages }
+}
@@ -2,6 +2,7 @@ use crate::property::PrResult;
 use crate::property::Property;
 use crate::types::os::Any;
 use crate::util::UserPathBuf;
+use crate::Facts;
 use std::collections::HashSet;
 use std::fmt;
 use std::fs;
@@ -57,7 +58,7 @@ impl fmt::Display for ContainsLines {
 }
 
 impl Property<Any> for ContainsLines {
-    fn check(&self) -> PrResult<bool> {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
         let existing = self.existing()?;
         for line in self.lines.iter() {
             if !existing.contains(line) {
@@ -67,7 +68,7 @@ impl Property<Any> for ContainsLines {
         Ok(true)
     }
 
-    fn apply(&self) -> PrResult<()> {
+    fn apply(&self, _: &Facts) -> PrResult<()> {
         let p = self.file.expand_user()?;
         let mut f = fs::OpenOptions::new().append(true).create(true).open(&p)?;
         let existing = self.existing()?;
@@ -81,6 +82,10 @@ impl Property<Any> for ContainsLines {
         f.write_all(new.join("\n").as_bytes())?;
         f.write_all("\n".as_bytes())
     }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
 }
 
 impl fmt::Display for ContentBytes {
@@ -96,7 +101,7 @@ impl fmt::Display for ContentBytes {
 }
 
 impl Property<Any> for ContentBytes {
-    fn check(&self) -> PrResult<bool> {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
         let p = self.path.expand_user()?;
         if !p.exists() {
             return Ok(false);
@@ -113,12 +118,13 @@ impl Property<Any> for ContentBytes {
         }
     }
 
-    fn apply(&self) -> PrResult<()> {
+    fn apply(&self, facts: &Facts) -> PrResult<()> {
         let p = self.path.expand_user()?;
         let f = fs::OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&p)?;
         let l = self.bytes.len() as u64;
         if f.metadata()?.len() > l {
@@ -138,7 +144,7 @@ impl Property<Any> for ContentBytes {
                 p
             );
         }
-        let ok = (self as &Property<Any>).check()?;
+        let ok = (self as &dyn Property<Any>).check(facts)?;
         if !ok {
             return Err(io::Error::new(
                 io::ErrorKind::Other,
@@ -147,6 +153,10 @@ impl Property<Any> for ContentBytes {
         }
         Ok(())
     }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
 }
 
 const BUF_SIZE: usize = 8 * 1024 * 32;
@@ -186,13 +196,7 @@ fn find_slice_not_eq<A: Eq>(this: &[A], other: &[A]) -> Option<usize> {
     let lhs = &this[..l];
     let rhs = &other[..l];
 
-    for i in 0..l {
-        if !lhs[i].eq(&rhs[i]) {
-            return Some(i);
-        }
-    }
-
-    None
+    (0..l).find(|&i| !lhs[i].eq(&rhs[i]))
 }
 
 #[cfg(test)]
@@ -230,33 +234,33 @@ mod tests {
         let path = tmp.join("file_content_bytes_test_1");
         let bs = "file_content_bytes_test_1_test_content";
         fs::write(&path, bs.as_bytes()).unwrap();
-        let prop: Box<Property<Any>> = Box::new(file(&path).content_bytes(bs.as_bytes()));
-        assert_eq!(prop.check().unwrap(), true);
-        prop.apply().unwrap();
-        assert_eq!(prop.check().unwrap(), true);
+        let prop: Box<dyn Property<Any>> = Box::new(file(&path).content_bytes(bs.as_bytes()));
+        assert!(prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
 
         fs::remove_file(&path).unwrap();
-        assert_eq!(prop.check().unwrap(), false);
-        prop.apply().unwrap();
-        assert_eq!(prop.check().unwrap(), true);
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
 
         fs::write(&path, &bs.as_bytes()[..5]).unwrap();
-        assert_eq!(prop.check().unwrap(), false);
-        prop.apply().unwrap();
-        assert_eq!(prop.check().unwrap(), true);
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
 
         let mut bs1: Vec<u8> = bs.as_bytes().to_vec();
         bs1[7] = b'^';
         fs::write(&path, &bs1).unwrap();
-        assert_eq!(prop.check().unwrap(), false);
-        prop.apply().unwrap();
-        assert_eq!(prop.check().unwrap(), true);
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
 
         let mut bs2: Vec<u8> = bs.as_bytes().to_vec();
         bs2.extend(b"abcde");
         fs::write(&path, &bs2).unwrap();
-        assert_eq!(prop.check().unwrap(), false);
-        prop.apply().unwrap();
-        assert_eq!(prop.check().unwrap(), true);
+        assert!(!prop.check(&Facts::default()).unwrap());
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
     }
 }
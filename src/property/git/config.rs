@@ -0,0 +1,487 @@
+//! Reading and writing files in the gitconfig format without git.
+//! Lines that are not changed are written back as they were, so
+//! comments, ordering and formatting are preserved.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A gitconfig file as a sequence of lines
+#[derive(Debug, Default)]
+pub struct ConfigFile {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug)]
+enum Line {
+    /// A section header such as `[core]` or `[url "https://..."]`
+    Section(SectionName),
+    /// A variable in the last section. A variable without `=` is
+    /// a boolean which is true.
+    Entry {
+        name: String,
+        value: Option<String>,
+        raw: String,
+    },
+    /// Comments and blank lines. Also section headers that can't
+    /// be parsed are kept as they are.
+    Other(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct SectionName {
+    /// Compared case-insensitively, stored in lower case
+    section: String,
+    /// Compared case-sensitively
+    subsection: Option<String>,
+    raw: String,
+}
+
+/// A key such as `core.editor` or `url.https://example.com/.insteadOf`
+#[derive(Debug, PartialEq)]
+struct Key<'a> {
+    section: String,
+    subsection: Option<&'a str>,
+    name: String,
+    /// The name as given, used when writing
+    given_name: &'a str,
+}
+
+impl<'a> Key<'a> {
+    fn parse(key: &'a str) -> io::Result<Key<'a>> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid git config key {}", key),
+            )
+        };
+        let first = key.find('.').ok_or_else(invalid)?;
+        let last = key.rfind('.').ok_or_else(invalid)?;
+        let section = key[..first].to_lowercase();
+        let name = key[last + 1..].to_lowercase();
+        if section.is_empty() || name.is_empty() {
+            return Err(invalid());
+        }
+        let subsection = if first == last {
+            None
+        } else {
+            Some(&key[first + 1..last])
+        };
+        Ok(Key {
+            section,
+            subsection,
+            name,
+            given_name: &key[last + 1..],
+        })
+    }
+
+    fn in_section(&self, section: &SectionName) -> bool {
+        section.section == self.section && section.subsection.as_deref() == self.subsection
+    }
+}
+
+impl ConfigFile {
+    /// Read a file; a missing file is empty
+    pub fn read(path: &Path) -> io::Result<ConfigFile> {
+        match fs::read_to_string(path) {
+            Ok(content) => ConfigFile::parse(&content),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(ConfigFile::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the file, creating its directory if needed
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_string())
+    }
+
+    pub fn parse(content: &str) -> io::Result<ConfigFile> {
+        let mut lines = vec![];
+        let mut raw_lines = content.lines();
+        while let Some(line) = raw_lines.next() {
+            let mut raw = line.to_string();
+            // A value may continue on the next line
+            while continues(&raw) && !is_comment(&raw) {
+                match raw_lines.next() {
+                    Some(next) => {
+                        raw.push('\n');
+                        raw.push_str(next);
+                    }
+                    None => break,
+                }
+            }
+            parse_line(raw, &mut lines)?;
+        }
+        Ok(ConfigFile { lines })
+    }
+
+    /// All values of the key in order. Booleans without values are `true`.
+    pub fn get_all(&self, key: &str) -> io::Result<Vec<String>> {
+        let key = Key::parse(key)?;
+        let mut values = vec![];
+        let mut in_section = false;
+        for line in &self.lines {
+            match line {
+                Line::Section(section) => in_section = key.in_section(section),
+                Line::Entry { name, value, .. } if in_section && *name == key.name => {
+                    values.push(value.clone().unwrap_or_else(|| "true".to_string()));
+                }
+                _ => {}
+            }
+        }
+        Ok(values)
+    }
+
+    /// Set the key to a single value, replacing the first existing
+    /// value and removing the others
+    pub fn replace_all(&mut self, key: &str, value: &str) -> io::Result<()> {
+        let parsed = Key::parse(key)?;
+        let positions = self.positions(&parsed);
+        match positions.first() {
+            Some(&first) => {
+                for &i in positions[1..].iter().rev() {
+                    self.lines.remove(i);
+                }
+                self.lines[first] = entry(&parsed, value);
+                Ok(())
+            }
+            None => self.add(key, value),
+        }
+    }
+
+    /// Add a value after the existing ones of a multi-valued key
+    pub fn add(&mut self, key: &str, value: &str) -> io::Result<()> {
+        let key = Key::parse(key)?;
+        let line = entry(&key, value);
+        if let Some(&last) = self.positions(&key).last() {
+            self.lines.insert(last + 1, line);
+            return Ok(());
+        }
+        // After the last entry of the last matching section
+        let mut end = None;
+        let mut in_section = false;
+        for (i, l) in self.lines.iter().enumerate() {
+            match l {
+                Line::Section(section) => {
+                    in_section = key.in_section(section);
+                    if in_section {
+                        end = Some(i);
+                    }
+                }
+                Line::Entry { .. } if in_section => end = Some(i),
+                _ => {}
+            }
+        }
+        match end {
+            Some(i) => self.lines.insert(i + 1, line),
+            None => {
+                self.lines.push(Line::Section(section_name(&key)));
+                self.lines.push(line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove all values of the key. Section headers are kept.
+    pub fn unset_all(&mut self, key: &str) -> io::Result<()> {
+        let key = Key::parse(key)?;
+        for i in self.positions(&key).into_iter().rev() {
+            self.lines.remove(i);
+        }
+        Ok(())
+    }
+
+    /// Indices of the lines containing values of the key
+    fn positions(&self, key: &Key) -> Vec<usize> {
+        let mut positions = vec![];
+        let mut in_section = false;
+        for (i, line) in self.lines.iter().enumerate() {
+            match line {
+                Line::Section(section) => in_section = key.in_section(section),
+                Line::Entry { name, .. } if in_section && *name == key.name => positions.push(i),
+                _ => {}
+            }
+        }
+        positions
+    }
+}
+
+impl std::fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for line in &self.lines {
+            let raw = match line {
+                Line::Section(section) => &section.raw,
+                Line::Entry { raw, .. } => raw,
+                Line::Other(raw) => raw,
+            };
+            writeln!(f, "{}", raw)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line ending in an odd number of backslashes continues on the
+/// next line; an even number are escaped backslashes
+fn continues(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+fn is_comment(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with('#') || line.starts_with(';')
+}
+
+/// Parse a logical line, which may be a section header followed
+/// by a variable
+fn parse_line(raw: String, lines: &mut Vec<Line>) -> io::Result<()> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() || is_comment(trimmed) {
+        lines.push(Line::Other(raw));
+        return Ok(());
+    }
+    if trimmed.starts_with('[') {
+        let (section, rest) = match parse_section(trimmed) {
+            Some(parsed) => parsed,
+            None => {
+                lines.push(Line::Other(raw));
+                return Ok(());
+            }
+        };
+        lines.push(Line::Section(section));
+        let rest = rest.trim();
+        if !rest.is_empty() && !is_comment(rest) {
+            // The variable is moved to its own line if the file is written
+            parse_line(rest.to_string(), lines)?;
+        }
+        return Ok(());
+    }
+    let name_len = trimmed
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(trimmed.len());
+    let name = trimmed[..name_len].to_lowercase();
+    let rest = trimmed[name_len..].trim_start();
+    let value = if let Some(value) = rest.strip_prefix('=') {
+        Some(parse_value(value)?)
+    } else if rest.is_empty() || is_comment(rest) {
+        None
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid line in git config: {}", raw),
+        ));
+    };
+    if name.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid line in git config: {}", raw),
+        ));
+    }
+    lines.push(Line::Entry { name, value, raw });
+    Ok(())
+}
+
+/// Parse a section header at the start of a line, returning the
+/// rest of the line
+fn parse_section(line: &str) -> Option<(SectionName, &str)> {
+    let inner = &line[1..];
+    if let Some(quote) = inner.find('"') {
+        // [section "subsection"]
+        let section = inner[..quote].trim().to_lowercase();
+        let mut subsection = String::new();
+        let mut chars = inner[quote + 1..].char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => subsection.extend(chars.next().map(|(_, c)| c)),
+                '"' => {
+                    end = Some(quote + 1 + i + 1);
+                    break;
+                }
+                c => subsection.push(c),
+            }
+        }
+        let after = inner[end?..].trim_start().strip_prefix(']')?;
+        let raw_len = line.len() - after.len();
+        let name = SectionName {
+            section,
+            subsection: Some(subsection),
+            raw: line[..raw_len].to_string(),
+        };
+        return Some((name, after));
+    }
+    let close = inner.find(']')?;
+    let header = inner[..close].trim();
+    // Deprecated [section.subsection] syntax with a lower case subsection
+    let (section, subsection) = match header.find('.') {
+        Some(dot) => (&header[..dot], Some(header[dot + 1..].to_lowercase())),
+        None => (header, None),
+    };
+    let name = SectionName {
+        section: section.to_lowercase(),
+        subsection,
+        raw: line[..close + 2].to_string(),
+    };
+    Some((name, &inner[close + 1..]))
+}
+
+/// Parse the part of a line after `=`
+fn parse_value(raw: &str) -> io::Result<String> {
+    let mut value = String::new();
+    let mut quoted = false;
+    // Length of the value without unquoted trailing whitespace
+    let mut len = 0;
+    let mut chars = raw.trim_start().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' | ';' if !quoted => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('b') => value.push('\u{8}'),
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                // Line continuation
+                Some('\n') => {}
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid escape in git config value: {}", raw),
+                    ))
+                }
+            },
+            c if c.is_whitespace() && !quoted => value.push(c),
+            c => value.push(c),
+        }
+        if quoted || !value.ends_with(char::is_whitespace) {
+            len = value.len();
+        }
+    }
+    value.truncate(len);
+    Ok(value)
+}
+
+/// A line for a variable, indented with a tab like git does
+fn entry(key: &Key, value: &str) -> Line {
+    Line::Entry {
+        name: key.name.clone(),
+        value: Some(value.to_string()),
+        raw: format!("\t{} = {}", key.given_name, quote_value(value)),
+    }
+}
+
+fn section_name(key: &Key) -> SectionName {
+    let raw = match key.subsection {
+        Some(sub) => {
+            let escaped = sub.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("[{} \"{}\"]", key.section, escaped)
+        }
+        None => format!("[{}]", key.section),
+    };
+    SectionName {
+        section: key.section.clone(),
+        subsection: key.subsection.map(|s| s.to_string()),
+        raw,
+    }
+}
+
+/// Escape a value, quoting it when whitespace at either end or
+/// comment characters would otherwise be lost
+fn quote_value(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(&['#', ';'][..]);
+    if needs_quotes {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "# user settings\n\
+[user]\n\
+\tname = \"Flandre \\\"S\\\"\" ; nickname\n\
+[Core]\n\
+\tquotePath=false\n\
+\tbare\n\
+[url \"https://github.com/\"]\n\
+\tinsteadOf = gh: # short\n\
+\tinsteadOf = github:\n\
+[include]\n\
+\tpath = a   \n";
+
+    #[test]
+    fn test_parse_get() {
+        let conf = ConfigFile::parse(SAMPLE).unwrap();
+        assert_eq!(conf.to_string(), SAMPLE);
+        assert_eq!(conf.get_all("user.name").unwrap(), vec!["Flandre \"S\""]);
+        assert_eq!(conf.get_all("core.quotepath").unwrap(), vec!["false"]);
+        assert_eq!(conf.get_all("core.bare").unwrap(), vec!["true"]);
+        assert_eq!(
+            conf.get_all("url.https://github.com/.insteadof").unwrap(),
+            vec!["gh:", "github:"]
+        );
+        assert_eq!(conf.get_all("include.path").unwrap(), vec!["a"]);
+        assert!(conf
+            .get_all("url.https://GitHub.com/.insteadOf")
+            .unwrap()
+            .is_empty());
+        assert!(conf.get_all("user").is_err());
+
+        let escaped = ConfigFile::parse("[a]\n\tb = c:\\\\\n\td = e\n").unwrap();
+        assert_eq!(escaped.get_all("a.b").unwrap(), vec!["c:\\"]);
+        assert_eq!(escaped.get_all("a.d").unwrap(), vec!["e"]);
+    }
+
+    #[test]
+    fn test_modify() {
+        let mut conf = ConfigFile::parse(SAMPLE).unwrap();
+        conf.replace_all("url.https://github.com/.insteadOf", "gh:")
+            .unwrap();
+        conf.add("include.path", "b").unwrap();
+        conf.unset_all("core.bare").unwrap();
+        conf.replace_all("core.editor", " vim ").unwrap();
+        conf.add("alias.l", "log # short").unwrap();
+        let expected = SAMPLE
+            .replace(
+                "\tinsteadOf = gh: # short\n\tinsteadOf = github:\n",
+                "\tinsteadOf = gh:\n",
+            )
+            .replace("\tpath = a   \n", "\tpath = a   \n\tpath = b\n")
+            .replace("\tbare\n", "\teditor = \" vim \"\n")
+            + "[alias]\n\tl = \"log # short\"\n";
+        assert_eq!(conf.to_string(), expected);
+        let conf = ConfigFile::parse(&expected).unwrap();
+        assert_eq!(conf.get_all("core.editor").unwrap(), vec![" vim "]);
+        assert_eq!(conf.get_all("alias.l").unwrap(), vec!["log # short"]);
+        assert_eq!(conf.get_all("include.path").unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_sections() {
+        let conf = ConfigFile::parse("[branch.Main] remote = origin\n[a \"b\\\"c\"]x=1").unwrap();
+        assert_eq!(conf.get_all("branch.main.remote").unwrap(), vec!["origin"]);
+        assert_eq!(conf.get_all("a.b\"c.x").unwrap(), vec!["1"]);
+        assert_eq!(
+            conf.to_string(),
+            "[branch.Main]\nremote = origin\n[a \"b\\\"c\"]\nx=1\n"
+        );
+    }
+}
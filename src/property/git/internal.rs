@@ -1,75 +1,233 @@
+use super::config::ConfigFile;
+use super::repo::git_output;
 use crate::os;
 use crate::property::Property;
+use crate::util::UserPathBuf;
+use crate::Facts;
 use crate::PrResult;
+use std::env;
 use std::fmt;
 use std::io;
-use std::process;
+use std::path::PathBuf;
 
-pub struct GitGlobalConfigKey {
+/// Which configuration file is used
+#[derive(Clone)]
+pub enum GitConfigScope {
+    /// The configuration of the user, like `--global`
+    Global,
+    /// The configuration of the system, like `--system`
+    System,
+    /// The configuration of a repository, like `--local`
+    Repo(UserPathBuf),
+    /// Any file in the gitconfig format, like `--file`
+    File(UserPathBuf),
+}
+
+pub struct GitConfigKey {
+    pub scope: GitConfigScope,
     pub key: &'static str,
 }
 
+/// What a key should be set to
+#[derive(Clone, Debug)]
+enum GitConfigState {
+    /// The key has this single value
+    Value(&'static str),
+    /// One of the values of a multi-valued key is this one
+    Includes(&'static str),
+    /// The key is not set
+    Unset,
+}
+
 #[derive(Clone)]
-pub struct GitGlobalConfig {
+pub struct GitConfig {
+    scope: GitConfigScope,
     key: &'static str,
-    value: &'static str,
+    state: GitConfigState,
 }
 
-impl GitGlobalConfigKey {
-    pub fn value(&self, value: &'static str) -> GitGlobalConfig {
-        GitGlobalConfig {
+impl GitConfigKey {
+    /// The key has exactly this value; other values of a
+    /// multi-valued key are replaced
+    pub fn value(&self, value: &'static str) -> GitConfig {
+        self.with_state(GitConfigState::Value(value))
+    }
+
+    /// The value is among the values of a multi-valued key,
+    /// such as `include.path`; it is added if missing
+    #[allow(dead_code)]
+    pub fn includes(&self, value: &'static str) -> GitConfig {
+        self.with_state(GitConfigState::Includes(value))
+    }
+
+    /// The key is not set; all of its values are removed
+    #[allow(dead_code)]
+    pub fn unset(&self) -> GitConfig {
+        self.with_state(GitConfigState::Unset)
+    }
+
+    fn with_state(&self, state: GitConfigState) -> GitConfig {
+        GitConfig {
+            scope: self.scope.clone(),
             key: self.key,
-            value,
+            state,
+        }
+    }
+}
+
+impl GitConfigScope {
+    /// The file git would write to for this scope
+    fn path(&self) -> io::Result<PathBuf> {
+        match self {
+            GitConfigScope::Global => {
+                let home = UserPathBuf::from(".gitconfig").expand_user()?;
+                // The XDG location is used only when ~/.gitconfig doesn't exist
+                let xdg = match env::var_os("XDG_CONFIG_HOME") {
+                    Some(config) if !config.is_empty() => PathBuf::from(config),
+                    _ => UserPathBuf::from(".config").expand_user()?,
+                }
+                .join("git/config");
+                if !home.exists() && xdg.exists() {
+                    Ok(xdg)
+                } else {
+                    Ok(home)
+                }
+            }
+            GitConfigScope::System => Ok(PathBuf::from("/etc/gitconfig")),
+            GitConfigScope::Repo(p) => {
+                // Worktrees and submodules have a `.git` file pointing
+                // elsewhere, so let git resolve it; a relative result is
+                // relative to the repository
+                let repo = p.expand_user()?;
+                let config = git_output(&repo, &["rev-parse", "--git-path", "config"])?;
+                Ok(repo.join(config))
+            }
+            GitConfigScope::File(p) => p.expand_user(),
         }
     }
 }
 
-impl fmt::Display for GitGlobalConfig {
+impl fmt::Display for GitConfigScope {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "git global config {}={}", self.key, self.value)?;
-        Ok(())
+        match self {
+            GitConfigScope::Global => write!(f, "global"),
+            GitConfigScope::System => write!(f, "system"),
+            GitConfigScope::Repo(p) => write!(f, "repository {:?}", p),
+            GitConfigScope::File(p) => write!(f, "file {:?}", p),
+        }
     }
 }
 
-impl Property<os::Any> for GitGlobalConfig {
-    fn check(&self) -> PrResult<bool> {
-        let mut out = process::Command::new("git")
-            .arg("config")
-            .arg("--null")
-            .arg("--global")
-            .arg(self.key)
-            .output()?
-            .stdout;
-        let null_end = out.last() == Some(&0);
-        if null_end {
-            out.truncate(out.len() - 1);
+impl fmt::Display for GitConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "git {} config ", self.scope)?;
+        match self.state {
+            GitConfigState::Value(value) => write!(f, "{}={}", self.key, value),
+            GitConfigState::Includes(value) => write!(f, "{} includes {}", self.key, value),
+            GitConfigState::Unset => write!(f, "{} is unset", self.key),
         }
-        let s =
-            String::from_utf8(out).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        if s == self.value {
-            Ok(true)
-        } else {
-            Ok(false)
+    }
+}
+
+impl Property<os::Any> for GitConfig {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let conf = ConfigFile::read(&self.scope.path()?)?;
+        let values = conf.get_all(self.key)?;
+        let ok = match self.state {
+            GitConfigState::Value(value) => values.len() == 1 && values[0] == value,
+            GitConfigState::Includes(value) => values.iter().any(|v| v == value),
+            GitConfigState::Unset => values.is_empty(),
+        };
+        Ok(ok)
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let path = self.scope.path()?;
+        let mut conf = ConfigFile::read(&path)?;
+        match self.state {
+            GitConfigState::Value(value) => conf.replace_all(self.key, value)?,
+            GitConfigState::Includes(value) => conf.add(self.key, value)?,
+            GitConfigState::Unset => conf.unset_all(self.key)?,
         }
+        conf.write(&path)
     }
 
-    fn apply(&self) -> PrResult<()> {
-        let s = process::Command::new("git")
-            .arg("config")
-            .arg("--global")
-            .arg(self.key)
-            .arg(self.value)
-            .status()?;
-        if !s.success() {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!(
-                    "git global config {}={} not set successfully",
-                    self.key, self.value
-                ),
-            ))
-        } else {
-            Ok(())
+    fn user_owned(&self) -> bool {
+        // The system configuration belongs to root
+        !matches!(self.scope, GitConfigScope::System)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property::git::{file_config, repo_config};
+    use std::env::temp_dir;
+    use std::fs;
+
+    #[test]
+    fn test_git_file_config() {
+        let path = temp_dir().join("git_file_config_test");
+        fs::write(&path, "[core]\n\tquotepath = true\n").unwrap();
+        let props: Vec<Box<dyn Property<os::Any>>> = vec![
+            Box::new(file_config(&path, "core.quotepath").value("false")),
+            Box::new(file_config(&path, "include.path").includes("a")),
+            Box::new(file_config(&path, "include.path").includes("b")),
+            Box::new(file_config(&path, "user.name").unset()),
+        ];
+        for prop in &props[..3] {
+            assert!(!prop.check(&Facts::default()).unwrap());
+            prop.apply(&Facts::default()).unwrap();
+            assert!(prop.check(&Facts::default()).unwrap());
         }
+        assert!(props[3].check(&Facts::default()).unwrap());
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("path = a") && content.contains("path = b"));
+
+        let unset = file_config(&path, "include.path").unset();
+        let unset: Box<dyn Property<os::Any>> = Box::new(unset);
+        assert!(!unset.check(&Facts::default()).unwrap());
+        unset.apply(&Facts::default()).unwrap();
+        assert!(unset.check(&Facts::default()).unwrap());
+        assert!(!props[1].check(&Facts::default()).unwrap());
+    }
+
+    #[test]
+    fn test_git_repo_config_worktree() {
+        let dir = temp_dir().join("git_repo_config_worktree_test");
+        let _ = fs::remove_dir_all(&dir);
+        let main = dir.join("main");
+        let worktree = dir.join("worktree");
+        fs::create_dir_all(&main).unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .current_dir(&main)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        git(&[
+            "-c",
+            "user.name=t",
+            "-c",
+            "user.email=t@t",
+            "commit",
+            "-q",
+            "--allow-empty",
+            "-m",
+            "init",
+        ]);
+        git(&["worktree", "add", "-q", worktree.to_str().unwrap()]);
+
+        let prop = repo_config(&worktree, "core.quotepath").value("false");
+        let prop: Box<dyn Property<os::Any>> = Box::new(prop);
+        prop.apply(&Facts::default()).unwrap();
+        assert!(prop.check(&Facts::default()).unwrap());
+        assert!(!worktree.join("config").exists());
+        let content = fs::read_to_string(main.join(".git/config")).unwrap();
+        assert!(content.contains("quotepath = false"));
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
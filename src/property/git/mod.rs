@@ -1,8 +1,41 @@
+mod config;
 mod internal;
+mod repo;
 
-use self::internal::GitGlobalConfigKey;
+use self::internal::{GitConfigKey, GitConfigScope};
+use self::repo::GitRepo;
+use crate::util::UserPathBuf;
+use std::sync::Arc;
 
 /// Set git config globally
-pub fn global(key: &'static str) -> GitGlobalConfigKey {
-    GitGlobalConfigKey { key }
+pub fn global(key: &'static str) -> GitConfigKey {
+    let scope = GitConfigScope::Global;
+    GitConfigKey { scope, key }
+}
+
+/// Set git config for all users of the system
+#[allow(dead_code)]
+pub fn system(key: &'static str) -> GitConfigKey {
+    let scope = GitConfigScope::System;
+    GitConfigKey { scope, key }
+}
+
+/// Set git config of a repository
+#[allow(dead_code)]
+pub fn repo_config<P: Into<UserPathBuf>>(repo: P, key: &'static str) -> GitConfigKey {
+    let scope = GitConfigScope::Repo(repo.into());
+    GitConfigKey { scope, key }
+}
+
+/// Set git config in a file, such as one included by other config files
+#[allow(dead_code)]
+pub fn file_config<P: Into<UserPathBuf>>(file: P, key: &'static str) -> GitConfigKey {
+    let scope = GitConfigScope::File(file.into());
+    GitConfigKey { scope, key }
+}
+
+/// A git repository at the path
+pub fn repo<P: Into<UserPathBuf>>(path: P) -> GitRepo {
+    let path = Arc::new(path.into());
+    GitRepo { path }
 }
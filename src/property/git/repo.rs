@@ -0,0 +1,359 @@
+//! A git repository cloned from a remote
+
+use crate::os;
+use crate::property::Property;
+use crate::util::UserPathBuf;
+use crate::Facts;
+use crate::PrResult;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+/// A directory managed as a git working tree
+pub struct GitRepo {
+    pub path: Arc<UserPathBuf>,
+}
+
+/// What should be checked out
+#[derive(Clone, Debug)]
+enum GitRef {
+    /// Whatever the remote HEAD points to when cloning
+    Default,
+    /// A branch tracking the branch with the same name on origin
+    Branch(&'static str),
+    /// A commit, tag or anything else git can resolve, detached
+    Rev(&'static str),
+}
+
+/// What is at the path of the repository
+enum Existing {
+    /// Nothing, or an empty directory, which can be cloned into
+    Missing,
+    /// The top level of a working tree
+    Repo,
+    /// Anything else, such as a directory inside another repository
+    Other,
+}
+
+/// The repository is cloned from a remote with some revision checked out
+#[derive(Clone)]
+pub struct GitCloned {
+    path: Arc<UserPathBuf>,
+    url: &'static str,
+    reference: GitRef,
+}
+
+impl GitRepo {
+    /// The repository is cloned from the url, which can also be a
+    /// local path. Its `origin` remote is set to the url.
+    pub fn cloned_from(&self, url: &'static str) -> GitCloned {
+        GitCloned {
+            path: self.path.clone(),
+            url,
+            reference: GitRef::Default,
+        }
+    }
+}
+
+impl GitCloned {
+    /// Check out a branch, fast-forwarded to the one on origin
+    #[allow(dead_code)]
+    pub fn branch(mut self, branch: &'static str) -> GitCloned {
+        self.reference = GitRef::Branch(branch);
+        self
+    }
+
+    /// Check out a commit or tag, detaching HEAD
+    #[allow(dead_code)]
+    pub fn rev(mut self, rev: &'static str) -> GitCloned {
+        self.reference = GitRef::Rev(rev);
+        self
+    }
+
+    /// Whether the requested revision is checked out, with a branch
+    /// up to date with origin
+    fn is_checked_out(&self, dir: &Path) -> io::Result<bool> {
+        match self.reference {
+            GitRef::Default => Ok(true),
+            GitRef::Branch(branch) => {
+                let head = git_output(dir, &["symbolic-ref", "--quiet", "--short", "HEAD"]);
+                if head.ok().as_deref() != Some(branch) {
+                    return Ok(false);
+                }
+                // Asks origin without fetching into the repository
+                let refname = format!("refs/heads/{}", branch);
+                let remote = git_output(dir, &["ls-remote", "origin", &refname])?;
+                let remote = match remote.split_whitespace().next() {
+                    Some(remote) => remote.to_string(),
+                    None => return Ok(false),
+                };
+                // Local commits on top of origin are kept
+                let head = git_output(dir, &["rev-parse", "HEAD"])?;
+                Ok(remote == head
+                    || git_output(dir, &["merge-base", "--is-ancestor", &remote, "HEAD"]).is_ok())
+            }
+            GitRef::Rev(rev) => {
+                let wanted = git_output(dir, &["rev-parse", "--verify", "--quiet", &commit(rev)]);
+                let head = git_output(dir, &["rev-parse", "HEAD"])?;
+                Ok(wanted.ok() == Some(head))
+            }
+        }
+    }
+
+    fn existing(dir: &Path) -> io::Result<Existing> {
+        if !dir.exists() || (dir.is_dir() && fs::read_dir(dir)?.next().is_none()) {
+            return Ok(Existing::Missing);
+        }
+        if !dir.is_dir() {
+            return Ok(Existing::Other);
+        }
+        // Inside another working tree, git would use that one instead
+        let top = git_output(dir, &["rev-parse", "--show-toplevel"]).ok();
+        match top {
+            Some(top) if Path::new(&top).canonicalize()? == dir.canonicalize()? => {
+                Ok(Existing::Repo)
+            }
+            _ => Ok(Existing::Other),
+        }
+    }
+
+    fn not_a_repo(dir: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{:?} is not empty and not a git repository", dir),
+        )
+    }
+
+    fn clone(&self, dir: &Path) -> io::Result<()> {
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let dir_str = dir.to_string_lossy();
+        let mut args = vec!["clone", "--quiet"];
+        if let GitRef::Branch(branch) = self.reference {
+            args.extend_from_slice(&["--branch", branch]);
+        }
+        args.extend_from_slice(&[self.url, &dir_str]);
+        println!("Cloning {} into {:?}", self.url, dir);
+        git_output(Path::new("."), &args)?;
+        if let GitRef::Rev(rev) = self.reference {
+            git_output(dir, &["checkout", "--quiet", "--detach", &commit(rev)])?;
+        }
+        Ok(())
+    }
+
+    fn update(&self, dir: &Path) -> io::Result<()> {
+        if is_dirty(dir)? {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{:?} has uncommitted changes, not updating", dir),
+            ));
+        }
+        if git_output(dir, &["remote", "get-url", "origin"])
+            .ok()
+            .as_deref()
+            != Some(self.url)
+        {
+            println!("Setting origin of {:?} to {}", dir, self.url);
+            // Fails if there is no origin yet
+            if git_output(dir, &["remote", "set-url", "origin", self.url]).is_err() {
+                git_output(dir, &["remote", "add", "origin", self.url])?;
+            }
+        }
+        println!("Fetching {} into {:?}", self.url, dir);
+        git_output(dir, &["fetch", "--quiet", "--tags", "origin"])?;
+        match self.reference {
+            GitRef::Default => {}
+            GitRef::Branch(branch) => {
+                // A local branch is created from origin if missing
+                git_output(dir, &["checkout", "--quiet", branch])?;
+                let upstream = format!("origin/{}", branch);
+                git_output(dir, &["merge", "--quiet", "--ff-only", &upstream])?;
+            }
+            GitRef::Rev(rev) => {
+                git_output(dir, &["checkout", "--quiet", "--detach", &commit(rev)])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for GitCloned {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "git repository {:?} is cloned from {}",
+            self.path, self.url
+        )?;
+        match self.reference {
+            GitRef::Default => Ok(()),
+            GitRef::Branch(branch) => write!(f, " with branch {} checked out", branch),
+            GitRef::Rev(rev) => write!(f, " with {} checked out", rev),
+        }
+    }
+}
+
+impl Property<os::Any> for GitCloned {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let dir = self.path.expand_user()?;
+        match GitCloned::existing(&dir)? {
+            Existing::Missing => return Ok(false),
+            Existing::Other => return Err(GitCloned::not_a_repo(&dir)),
+            Existing::Repo => {}
+        }
+        let origin = git_output(&dir, &["remote", "get-url", "origin"]).ok();
+        if origin.as_deref() != Some(self.url) || !self.is_checked_out(&dir)? {
+            if is_dirty(&dir)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("{:?} has uncommitted changes", dir),
+                ));
+            }
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let dir = self.path.expand_user()?;
+        match GitCloned::existing(&dir)? {
+            Existing::Missing => self.clone(&dir),
+            Existing::Repo => self.update(&dir),
+            Existing::Other => Err(GitCloned::not_a_repo(&dir)),
+        }
+    }
+
+    fn user_owned(&self) -> bool {
+        true
+    }
+}
+
+/// Make git resolve a name only to a commit
+fn commit(rev: &str) -> String {
+    format!("{}^{{commit}}", rev)
+}
+
+/// Whether there are uncommitted changes, including untracked files
+fn is_dirty(dir: &Path) -> io::Result<bool> {
+    let status = git_output(dir, &["status", "--porcelain"])?;
+    Ok(!status.is_empty())
+}
+
+/// Run git in a directory, returning the trimmed standard output.
+/// A non-zero exit status is an error containing the standard error.
+pub(super) fn git_output(dir: &Path, args: &[&str]) -> io::Result<String> {
+    let out = process::Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("git {} failed: {}", args.join(" "), stderr.trim()),
+        ));
+    }
+    let stdout =
+        String::from_utf8(out.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(stdout.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property::git::repo;
+    use std::env::temp_dir;
+
+    fn commit_file(dir: &Path, name: &str) -> String {
+        fs::write(dir.join(name), name).unwrap();
+        git_output(dir, &["add", name]).unwrap();
+        git_output(
+            dir,
+            &[
+                "-c",
+                "user.name=rotor",
+                "-c",
+                "user.email=rotor@localhost",
+                "commit",
+                "--quiet",
+                "-m",
+                name,
+            ],
+        )
+        .unwrap();
+        git_output(dir, &["rev-parse", "HEAD"]).unwrap()
+    }
+
+    #[test]
+    fn test_git_cloned() {
+        let base = temp_dir().join("git_cloned_test");
+        if base.exists() {
+            fs::remove_dir_all(&base).unwrap();
+        }
+        let origin = base.join("origin");
+        let clone = base.join("clone");
+        fs::create_dir_all(&origin).unwrap();
+        git_output(&origin, &["init", "--quiet"]).unwrap();
+        git_output(&origin, &["checkout", "--quiet", "-b", "main"]).unwrap();
+        let first = commit_file(&origin, "a");
+        git_output(&origin, &["checkout", "--quiet", "-b", "dev"]).unwrap();
+        commit_file(&origin, "b");
+        git_output(&origin, &["checkout", "--quiet", "main"]).unwrap();
+
+        // Properties take static strings
+        let url: &'static str = Box::leak(origin.to_string_lossy().into_owned().into_boxed_str());
+        let first: &'static str = Box::leak(first[..10].to_string().into_boxed_str());
+        let cloned: Box<dyn Property<os::Any>> = Box::new(repo(&clone).cloned_from(url));
+        assert!(!cloned.check(&Facts::default()).unwrap());
+        cloned.apply(&Facts::default()).unwrap();
+        assert!(cloned.check(&Facts::default()).unwrap());
+        assert!(clone.join("a").exists());
+
+        let branch: Box<dyn Property<os::Any>> =
+            Box::new(repo(&clone).cloned_from(url).branch("dev"));
+        assert!(!branch.check(&Facts::default()).unwrap());
+        branch.apply(&Facts::default()).unwrap();
+        assert!(branch.check(&Facts::default()).unwrap());
+        assert!(clone.join("b").exists());
+
+        // New commits on origin are fast-forwarded
+        git_output(&origin, &["checkout", "--quiet", "dev"]).unwrap();
+        commit_file(&origin, "c");
+        git_output(&origin, &["checkout", "--quiet", "main"]).unwrap();
+        assert!(!branch.check(&Facts::default()).unwrap());
+        branch.apply(&Facts::default()).unwrap();
+        assert!(branch.check(&Facts::default()).unwrap());
+        assert!(clone.join("c").exists());
+
+        let rev: Box<dyn Property<os::Any>> = Box::new(repo(&clone).cloned_from(url).rev(first));
+        assert!(!rev.check(&Facts::default()).unwrap());
+        // Uncommitted changes are not clobbered
+        fs::write(clone.join("b"), "changed").unwrap();
+        assert!(rev.check(&Facts::default()).is_err());
+        assert!(rev.apply(&Facts::default()).is_err());
+        git_output(&clone, &["checkout", "--quiet", "b"]).unwrap();
+        rev.apply(&Facts::default()).unwrap();
+        assert!(rev.check(&Facts::default()).unwrap());
+        assert!(!clone.join("b").exists());
+
+        // A directory inside a working tree is not that repository
+        let nested = clone.join("nested");
+        fs::create_dir_all(nested.join("sub")).unwrap();
+        let inner: Box<dyn Property<os::Any>> = Box::new(repo(&nested).cloned_from(url));
+        assert!(inner.check(&Facts::default()).is_err());
+        assert!(inner.apply(&Facts::default()).is_err());
+        assert_eq!(
+            git_output(&clone, &["remote", "get-url", "origin"]).unwrap(),
+            url
+        );
+        // but an empty one is cloned into
+        fs::remove_dir(nested.join("sub")).unwrap();
+        assert!(!inner.check(&Facts::default()).unwrap());
+        inner.apply(&Facts::default()).unwrap();
+        assert!(inner.check(&Facts::default()).unwrap());
+        assert!(nested.join("a").exists());
+    }
+}
@@ -0,0 +1,102 @@
+//! Installed packages, queried once per run with a single command
+//! instead of once per package. The cache is invalidated whenever
+//! packages are installed or removed.
+
+use std::collections::HashMap;
+use std::io;
+use std::process;
+use std::sync::Mutex;
+
+/// Installed packages of one package manager, by default
+/// their names and versions
+pub struct InstalledCache<T = HashMap<String, String>> {
+    packages: Mutex<Option<T>>,
+}
+
+impl<T> InstalledCache<T> {
+    pub const fn new() -> InstalledCache<T> {
+        InstalledCache {
+            packages: Mutex::new(None),
+        }
+    }
+
+    /// Look at the installed packages. The query is only run if
+    /// nothing is cached.
+    pub fn with<Q, F, R>(&self, query: Q, f: F) -> io::Result<R>
+    where
+        Q: FnOnce() -> io::Result<T>,
+        F: FnOnce(&T) -> R,
+    {
+        let mut cached = self.packages.lock().unwrap_or_else(|e| e.into_inner());
+        if cached.is_none() {
+            *cached = Some(query()?);
+        }
+        Ok(f(cached.as_ref().expect("Installed packages just cached")))
+    }
+
+    /// Forget installed packages after changing them
+    pub fn invalidate(&self) {
+        *self.packages.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+impl InstalledCache {
+    /// Packages which are not installed, in the given order
+    pub fn missing<'a, F>(&self, packages: &[&'a str], query: F) -> io::Result<Vec<&'a str>>
+    where
+        F: FnOnce() -> io::Result<HashMap<String, String>>,
+    {
+        self.with(query, |installed| {
+            packages
+                .iter()
+                .filter(|&&p| !installed.contains_key(p))
+                .cloned()
+                .collect()
+        })
+    }
+}
+
+/// Run a query command with its output captured, returning
+/// standard output. Fails if the command fails.
+pub fn query_output(cmd: &mut process::Command) -> io::Result<String> {
+    let out = cmd.stdin(process::Stdio::null()).output()?;
+    if !out.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "{:?} failed: {}",
+                cmd,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        ));
+    }
+    String::from_utf8(out.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_installed_cache() {
+        let cache: InstalledCache = InstalledCache::new();
+        let queries = Cell::new(0);
+        let query = || {
+            queries.set(queries.get() + 1);
+            Ok(["bash", "vim"]
+                .iter()
+                .map(|s| (s.to_string(), "1.0-1".to_string()))
+                .collect())
+        };
+        assert_eq!(
+            cache.missing(&["vim", "tdrop"], query).unwrap(),
+            vec!["tdrop"]
+        );
+        assert!(cache.missing(&["bash"], query).unwrap().is_empty());
+        assert_eq!(queries.get(), 1);
+        cache.invalidate();
+        assert!(cache.missing(&["bash"], query).unwrap().is_empty());
+        assert_eq!(queries.get(), 2);
+    }
+}
@@ -1,23 +1,35 @@
 use std::fmt::Display;
 
+pub mod apk;
 pub mod apt;
+pub mod aur;
 pub mod conf_file;
 mod core;
 pub mod dir;
+pub mod dnf;
 mod file;
 pub mod git;
+mod installed;
 pub mod pacman;
+mod version;
 
 pub use self::core::{prop, PropertyList};
 pub use self::file::file;
 
 use self::core::PropertyClone;
 use super::types::os::{self, OS};
-use crate::PrResult;
+use crate::{Facts, PrResult};
 
 pub trait Property<O: OS>: Display + PropertyClone<O> {
-    fn check(&self) -> PrResult<bool>;
-    fn apply(&self) -> PrResult<()>;
+    fn check(&self, facts: &Facts) -> PrResult<bool>;
+    fn apply(&self, facts: &Facts) -> PrResult<()>;
+
+    /// Whether the property is about files of the user, wherever they
+    /// are. When root configures another user, such properties run as
+    /// that user with facts about that user, others as root.
+    fn user_owned(&self) -> bool {
+        false
+    }
 }
 
 // Properties that apply to any OS also apply to Linux
@@ -25,11 +37,14 @@ impl<T> Property<os::Linux> for T
 where
     T: Property<os::Any> + Clone + 'static,
 {
-    fn check(&self) -> PrResult<bool> {
-        T::check(self)
+    fn check(&self, facts: &Facts) -> PrResult<bool> {
+        T::check(self, facts)
+    }
+    fn apply(&self, facts: &Facts) -> PrResult<()> {
+        T::apply(self, facts)
     }
-    fn apply(&self) -> PrResult<()> {
-        T::apply(self)
+    fn user_owned(&self) -> bool {
+        T::user_owned(self)
     }
 }
 
@@ -38,11 +53,14 @@ impl<T> Property<os::ArchLinux> for T
 where
     T: Property<os::Linux> + Clone + 'static,
 {
-    fn check(&self) -> PrResult<bool> {
-        T::check(self)
+    fn check(&self, facts: &Facts) -> PrResult<bool> {
+        T::check(self, facts)
     }
-    fn apply(&self) -> PrResult<()> {
-        T::apply(self)
+    fn apply(&self, facts: &Facts) -> PrResult<()> {
+        T::apply(self, facts)
+    }
+    fn user_owned(&self) -> bool {
+        T::user_owned(self)
     }
 }
 
@@ -51,10 +69,45 @@ impl<T> Property<os::DebianLike> for T
 where
     T: Property<os::Linux> + Clone + 'static,
 {
-    fn check(&self) -> PrResult<bool> {
-        T::check(self)
+    fn check(&self, facts: &Facts) -> PrResult<bool> {
+        T::check(self, facts)
+    }
+    fn apply(&self, facts: &Facts) -> PrResult<()> {
+        T::apply(self, facts)
+    }
+    fn user_owned(&self) -> bool {
+        T::user_owned(self)
+    }
+}
+
+// Properties that apply to any Linux also apply to Red Hat derivatives
+impl<T> Property<os::RedHatLike> for T
+where
+    T: Property<os::Linux> + Clone + 'static,
+{
+    fn check(&self, facts: &Facts) -> PrResult<bool> {
+        T::check(self, facts)
+    }
+    fn apply(&self, facts: &Facts) -> PrResult<()> {
+        T::apply(self, facts)
+    }
+    fn user_owned(&self) -> bool {
+        T::user_owned(self)
+    }
+}
+
+// Properties that apply to any Linux also apply to Alpine
+impl<T> Property<os::Alpine> for T
+where
+    T: Property<os::Linux> + Clone + 'static,
+{
+    fn check(&self, facts: &Facts) -> PrResult<bool> {
+        T::check(self, facts)
+    }
+    fn apply(&self, facts: &Facts) -> PrResult<()> {
+        T::apply(self, facts)
     }
-    fn apply(&self) -> PrResult<()> {
-        T::apply(self)
+    fn user_owned(&self) -> bool {
+        T::user_owned(self)
     }
 }
@@ -0,0 +1,538 @@
+//! Options and repositories in pacman.conf, which has INI sections,
+//! bare flags such as `Color` and repeated keys such as `Server`.
+//! Commented defaults are uncommented when possible.
+
+use super::internal::installed_changed;
+use crate::os;
+use crate::property::Property;
+use crate::util::UserPathBuf;
+use crate::Facts;
+use crate::PrResult;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::process;
+use std::sync::Arc;
+
+const OPTIONS: &str = "options";
+pub(super) const PACMAN_CONF: &str = "/etc/pacman.conf";
+const MIRRORLIST: &str = "/etc/pacman.d/mirrorlist";
+/// Keys allowed in a repository section
+const REPO_KEYS: &[&str] = &["Server", "Include", "SigLevel", "Usage", "CacheServer"];
+
+/// A pacman configuration file
+pub struct PacmanConfFile {
+    pub path: Arc<UserPathBuf>,
+}
+
+/// An option in the `[options]` section has a value, or is a flag
+/// if there's no value
+#[derive(Clone)]
+pub struct PacmanConfOption {
+    path: Arc<UserPathBuf>,
+    key: &'static str,
+    value: Option<&'static str>,
+}
+
+/// A repository is enabled, using the default mirrorlist unless it
+/// is already configured
+#[derive(Clone)]
+pub struct PacmanRepoEnabled {
+    path: Arc<UserPathBuf>,
+    name: &'static str,
+}
+
+/// A repository with exactly the given servers and signature level
+#[derive(Clone)]
+pub struct PacmanRepo {
+    path: Arc<UserPathBuf>,
+    name: &'static str,
+    servers: Vec<&'static str>,
+    sig_level: Option<&'static str>,
+}
+
+impl PacmanConfFile {
+    /// An option in `[options]` has a value, such as `ParallelDownloads = 5`
+    pub fn option(&self, key: &'static str, value: &'static str) -> PacmanConfOption {
+        PacmanConfOption {
+            path: self.path.clone(),
+            key,
+            value: Some(value),
+        }
+    }
+
+    /// A flag in `[options]` is set, such as `Color`
+    pub fn flag(&self, key: &'static str) -> PacmanConfOption {
+        PacmanConfOption {
+            path: self.path.clone(),
+            key,
+            value: None,
+        }
+    }
+
+    /// A repository such as `multilib` is enabled
+    pub fn repo_enabled(&self, name: &'static str) -> PacmanRepoEnabled {
+        PacmanRepoEnabled {
+            path: self.path.clone(),
+            name,
+        }
+    }
+
+    /// A custom repository; add servers with `server`
+    pub fn repo(&self, name: &'static str) -> PacmanRepo {
+        PacmanRepo {
+            path: self.path.clone(),
+            name,
+            servers: vec![],
+            sig_level: None,
+        }
+    }
+}
+
+impl PacmanRepo {
+    /// Add a server, such as `https://example.com/$repo/$arch`
+    pub fn server(mut self, url: &'static str) -> PacmanRepo {
+        self.servers.push(url);
+        self
+    }
+
+    /// Set the signature level, such as `Optional TrustAll`
+    pub fn sig_level(mut self, level: &'static str) -> PacmanRepo {
+        self.sig_level = Some(level);
+        self
+    }
+
+    fn entries(&self) -> Vec<(&'static str, Option<&'static str>)> {
+        let mut entries = vec![];
+        if let Some(level) = self.sig_level {
+            entries.push(("SigLevel", Some(level)));
+        }
+        for &server in &self.servers {
+            entries.push(("Server", Some(server)));
+        }
+        entries
+    }
+}
+
+/// Lines of pacman.conf
+#[derive(Debug)]
+struct PacmanConf {
+    lines: Vec<String>,
+}
+
+/// Split a line into key and value, ignoring comments
+fn key_value(line: &str) -> Option<(&str, Option<&str>)> {
+    let line = line.split('#').next().unwrap_or("").trim();
+    if line.is_empty() || line.starts_with('[') {
+        return None;
+    }
+    let mut parts = line.splitn(2, '=');
+    let key = parts.next()?.trim();
+    let value = parts.next().map(|v| v.trim());
+    Some((key, value))
+}
+
+/// The content of a commented line
+fn uncommented(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix('#').map(|l| l.trim_start())
+}
+
+fn entry_line(key: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("{} = {}", key, value),
+        None => key.to_string(),
+    }
+}
+
+impl PacmanConf {
+    fn parse(content: &str) -> PacmanConf {
+        let lines = content.lines().map(|l| l.to_string()).collect();
+        PacmanConf { lines }
+    }
+
+    fn read(path: &UserPathBuf) -> io::Result<PacmanConf> {
+        Ok(PacmanConf::parse(&fs::read_to_string(path.expand_user()?)?))
+    }
+
+    fn write(&self, path: &UserPathBuf) -> io::Result<()> {
+        let mut content = self.lines.join("\n");
+        content.push('\n');
+        fs::write(path.expand_user()?, content)
+    }
+
+    /// Range of lines in a section after its header, and the index
+    /// of the header
+    fn section(&self, name: &str) -> Option<(usize, usize)> {
+        let header = format!("[{}]", name);
+        let start = self.lines.iter().position(|l| l.trim() == header)?;
+        Some((start, self.section_end(start)))
+    }
+
+    /// Index of the next header after the given one, which may be
+    /// commented out, or the end of the file
+    fn next_header(&self, start: usize) -> usize {
+        self.lines[start + 1..]
+            .iter()
+            .position(|l| {
+                let l = l.trim();
+                l.starts_with('[') || uncommented(l).is_some_and(|l| l.starts_with('['))
+            })
+            .map_or(self.lines.len(), |i| start + 1 + i)
+    }
+
+    /// Index after the last line of the section starting at the given
+    /// header, before any comments preceding the next section
+    fn section_end(&self, start: usize) -> usize {
+        // Blank lines and comments before the next section belong to it
+        let mut end = self.next_header(start);
+        while end > start + 1 && key_value(&self.lines[end - 1]).is_none() {
+            end -= 1;
+        }
+        end
+    }
+
+    /// Values of a key in a section; flags have no value
+    fn get(&self, section: &str, key: &str) -> Vec<Option<String>> {
+        let (start, end) = match self.section(section) {
+            Some(range) => range,
+            None => return vec![],
+        };
+        self.lines[start + 1..end]
+            .iter()
+            .filter_map(|l| key_value(l))
+            .filter(|(k, _)| *k == key)
+            .map(|(_, v)| v.map(|v| v.to_string()))
+            .collect()
+    }
+
+    /// Set a single value of a key in a section, replacing the first
+    /// occurrence or uncommenting a commented default
+    fn set(&mut self, section: &str, key: &str, value: Option<&str>) {
+        let line = entry_line(key, value);
+        let (start, end) = match self.section(section) {
+            Some(range) => range,
+            None => {
+                self.append_section(section, &[(key, value)]);
+                return;
+            }
+        };
+        let existing: Vec<usize> = (start + 1..end)
+            .filter(|&i| key_value(&self.lines[i]).map(|(k, _)| k) == Some(key))
+            .collect();
+        if let Some(&first) = existing.first() {
+            for &i in existing[1..].iter().rev() {
+                self.lines.remove(i);
+            }
+            self.lines[first] = line;
+            return;
+        }
+        // Commented defaults may follow the last active entry
+        let commented = (start + 1..self.next_header(start)).find(|&i| {
+            uncommented(&self.lines[i])
+                .and_then(key_value)
+                .map(|(k, _)| k)
+                == Some(key)
+        });
+        match commented {
+            Some(i) => self.lines[i] = line,
+            None => self.lines.insert(end, line),
+        }
+    }
+
+    /// Uncomment a section and the commented repository entries
+    /// following it, up to the first blank line or other comment
+    fn uncomment_section(&mut self, name: &str) -> bool {
+        let header = format!("[{}]", name);
+        let start = match self
+            .lines
+            .iter()
+            .position(|l| uncommented(l).map(|l| l.trim()) == Some(header.as_str()))
+        {
+            Some(i) => i,
+            None => return false,
+        };
+        self.lines[start] = header;
+        let mut i = start + 1;
+        while i < self.lines.len() {
+            match uncommented(&self.lines[i]).and_then(key_value) {
+                Some((key, value)) if REPO_KEYS.contains(&key) => {
+                    self.lines[i] = entry_line(key, value)
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        true
+    }
+
+    /// Replace the entries of a section, or add it at the end
+    fn set_section(&mut self, name: &str, entries: &[(&str, Option<&str>)]) {
+        match self.section(name) {
+            Some((start, end)) => {
+                let lines = entries.iter().map(|&(k, v)| entry_line(k, v));
+                self.lines.splice(start + 1..end, lines);
+            }
+            None => self.append_section(name, entries),
+        }
+    }
+
+    fn append_section(&mut self, name: &str, entries: &[(&str, Option<&str>)]) {
+        if self.lines.last().is_some_and(|l| !l.trim().is_empty()) {
+            self.lines.push(String::new());
+        }
+        self.lines.push(format!("[{}]", name));
+        for &(key, value) in entries {
+            self.lines.push(entry_line(key, value));
+        }
+    }
+}
+
+impl PacmanConf {
+    /// Packages in `IgnorePkg`, which may be repeated and lists
+    /// packages separated by spaces
+    fn ignored(&self) -> Vec<String> {
+        self.get(OPTIONS, "IgnorePkg")
+            .into_iter()
+            .flatten()
+            .flat_map(|v| {
+                v.split_whitespace()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Add packages to `IgnorePkg`, merged into a single line
+    fn ignore(&mut self, packages: &[&str]) {
+        let mut ignored = self.ignored();
+        for &package in packages {
+            if !ignored.iter().any(|p| p == package) {
+                ignored.push(package.to_string());
+            }
+        }
+        self.set(OPTIONS, "IgnorePkg", Some(&ignored.join(" ")));
+    }
+}
+
+/// Packages held back from upgrades in a pacman configuration file
+pub(super) fn ignored_packages(path: &UserPathBuf) -> io::Result<Vec<String>> {
+    Ok(PacmanConf::read(path)?.ignored())
+}
+
+/// Hold packages back from upgrades
+pub(super) fn ignore_packages(path: &UserPathBuf, packages: &[&str]) -> io::Result<()> {
+    let mut conf = PacmanConf::read(path)?;
+    conf.ignore(packages);
+    conf.write(path)
+}
+
+/// Refresh package databases after changing repositories
+fn sync_databases(path: &UserPathBuf) -> io::Result<()> {
+    let path = path.expand_user()?;
+    let s = process::Command::new("pacman")
+        .arg("--config")
+        .arg(&path)
+        .arg("-Sy")
+        .status()?;
+    installed_changed();
+    if !s.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "pacman failed to synchronize package databases",
+        ));
+    }
+    Ok(())
+}
+
+impl fmt::Display for PacmanConfOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Some(value) => write!(
+                f,
+                "pacman option {} = {} in {:?}",
+                self.key, value, self.path
+            ),
+            None => write!(f, "pacman option {} is set in {:?}", self.key, self.path),
+        }
+    }
+}
+
+impl fmt::Display for PacmanRepoEnabled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pacman repository {} is enabled in {:?}",
+            self.name, self.path
+        )
+    }
+}
+
+impl fmt::Display for PacmanRepo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pacman repository {} uses servers {:?} in {:?}",
+            self.name, self.servers, self.path
+        )
+    }
+}
+
+impl Property<os::ArchLinux> for PacmanConfOption {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let conf = PacmanConf::read(&self.path)?;
+        let expected = self.value.map(|v| v.to_string());
+        Ok(conf.get(OPTIONS, self.key) == vec![expected])
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let mut conf = PacmanConf::read(&self.path)?;
+        conf.set(OPTIONS, self.key, self.value);
+        conf.write(&self.path)
+    }
+}
+
+impl Property<os::ArchLinux> for PacmanRepoEnabled {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let conf = PacmanConf::read(&self.path)?;
+        let configured =
+            !conf.get(self.name, "Include").is_empty() || !conf.get(self.name, "Server").is_empty();
+        Ok(configured)
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let mut conf = PacmanConf::read(&self.path)?;
+        conf.uncomment_section(self.name);
+        let configured =
+            !conf.get(self.name, "Include").is_empty() || !conf.get(self.name, "Server").is_empty();
+        if !configured {
+            conf.set(self.name, "Include", Some(MIRRORLIST));
+        }
+        conf.write(&self.path)?;
+        sync_databases(&self.path)
+    }
+}
+
+impl Property<os::ArchLinux> for PacmanRepo {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let conf = PacmanConf::read(&self.path)?;
+        let servers: Vec<Option<String>> =
+            self.servers.iter().map(|s| Some(s.to_string())).collect();
+        let sig_level: Vec<Option<String>> =
+            self.sig_level.iter().map(|s| Some(s.to_string())).collect();
+        Ok(conf.get(self.name, "Server") == servers
+            && conf.get(self.name, "SigLevel") == sig_level
+            && conf.get(self.name, "Include").is_empty())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let mut conf = PacmanConf::read(&self.path)?;
+        conf.set_section(self.name, &self.entries());
+        conf.write(&self.path)?;
+        sync_databases(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+[options]
+#Color
+#ParallelDownloads = 5
+#IgnorePkg   =
+HoldPkg = pacman glibc
+#RemoteFileSigLevel = Required
+
+# The testing repositories are disabled by default.
+
+[core]
+Include = /etc/pacman.d/mirrorlist
+
+#[multilib]
+#Include = /etc/pacman.d/mirrorlist
+#An example of a custom package repository. See the pacman manpage for
+#tips on creating your own repositories.
+#[custom]
+#SigLevel = Optional TrustAll
+#Server = file:///home/custompkgs
+
+#Usage = Sync
+";
+
+    #[test]
+    fn test_options() {
+        let mut conf = PacmanConf::parse(SAMPLE);
+        assert!(conf.get(OPTIONS, "Color").is_empty());
+        conf.set(OPTIONS, "Color", None);
+        conf.set(OPTIONS, "ParallelDownloads", Some("8"));
+        conf.set(OPTIONS, "VerbosePkgLists", None);
+        conf.set(OPTIONS, "RemoteFileSigLevel", Some("Never"));
+        assert_eq!(conf.get(OPTIONS, "Color"), vec![None]);
+        assert_eq!(
+            conf.get(OPTIONS, "ParallelDownloads"),
+            vec![Some("8".to_string())]
+        );
+        let expected = SAMPLE
+            .replace("#Color", "Color")
+            .replace("#ParallelDownloads = 5", "ParallelDownloads = 8")
+            .replace("glibc\n", "glibc\nVerbosePkgLists\n")
+            .replace(
+                "#RemoteFileSigLevel = Required",
+                "RemoteFileSigLevel = Never",
+            );
+        assert_eq!(conf.lines.join("\n") + "\n", expected);
+    }
+
+    #[test]
+    fn test_repos() {
+        let mut conf = PacmanConf::parse(SAMPLE);
+        assert!(conf.get("multilib", "Include").is_empty());
+        assert!(conf.uncomment_section("multilib"));
+        assert_eq!(
+            conf.get("multilib", "Include"),
+            vec![Some(MIRRORLIST.to_string())]
+        );
+
+        conf.set_section(
+            "custom",
+            &[("SigLevel", Some("Optional")), ("Server", Some("a"))],
+        );
+        conf.set_section("custom", &[("Server", Some("b")), ("Server", Some("c"))]);
+        assert!(conf.get("custom", "SigLevel").is_empty());
+        assert_eq!(
+            conf.get("custom", "Server"),
+            vec![Some("b".to_string()), Some("c".to_string())]
+        );
+        let expected = SAMPLE.replace("#[multilib]\n#Include", "[multilib]\nInclude")
+            + "\n[custom]\nServer = b\nServer = c\n";
+        assert_eq!(conf.lines.join("\n") + "\n", expected);
+
+        // Prose and entries after a blank line stay commented
+        let mut conf = PacmanConf::parse(SAMPLE);
+        assert!(conf.uncomment_section("custom"));
+        let expected = SAMPLE.replace(
+            "#[custom]\n#SigLevel = Optional TrustAll\n#Server",
+            "[custom]\nSigLevel = Optional TrustAll\nServer",
+        );
+        assert_eq!(conf.lines.join("\n") + "\n", expected);
+    }
+
+    #[test]
+    fn test_ignored() {
+        let mut conf = PacmanConf::parse(SAMPLE);
+        assert!(conf.ignored().is_empty());
+        conf.ignore(&["linux"]);
+        let expected = SAMPLE.replace("#IgnorePkg   =", "IgnorePkg = linux");
+        assert_eq!(conf.lines.join("\n") + "\n", expected);
+
+        let repeated = SAMPLE.replace("#IgnorePkg   =", "IgnorePkg = linux\nIgnorePkg = nvidia");
+        let mut conf = PacmanConf::parse(&repeated);
+        assert_eq!(conf.ignored(), vec!["linux", "nvidia"]);
+        conf.ignore(&["nvidia", "linux-headers"]);
+        assert_eq!(
+            conf.get(OPTIONS, "IgnorePkg"),
+            vec![Some("linux nvidia linux-headers".to_string())]
+        );
+    }
+}
@@ -1,14 +1,92 @@
+use super::conf::{ignore_packages, ignored_packages, PACMAN_CONF};
 use crate::os;
+use crate::property::installed::{query_output, InstalledCache};
+use crate::property::version::{pacman_cmp, VersionReq};
 use crate::property::Property;
+use crate::util::UserPathBuf;
+use crate::Facts;
 use crate::PrResult;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fmt;
+use std::fs;
 use std::io;
+use std::os::unix::fs::symlink;
+use std::path::Path;
 use std::process;
 
+/// Packages installed by pacman, shared by all properties
+static INSTALLED: InstalledCache = InstalledCache::new();
+
+/// Names and versions of all installed packages
+fn query_installed() -> io::Result<HashMap<String, String>> {
+    let out = query_output(process::Command::new("pacman").arg("-Q"))?;
+    Ok(out
+        .lines()
+        .filter_map(|l| {
+            let mut fields = l.split_whitespace();
+            let name = fields.next()?.to_string();
+            let version = fields.next().unwrap_or("").to_string();
+            Some((name, version))
+        })
+        .collect())
+}
+
+/// Run a pacman query listing package names. Queries exit with 1
+/// when nothing matches, which is an empty list.
+pub(crate) fn query_names(args: &[&str]) -> io::Result<Vec<String>> {
+    let out = process::Command::new("pacman")
+        .args(args)
+        .stdin(process::Stdio::null())
+        .output()?;
+    if !out.status.success() {
+        if out.status.code() == Some(1) && out.stdout.is_empty() {
+            return Ok(vec![]);
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "pacman {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        ));
+    }
+    let out =
+        String::from_utf8(out.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(out.lines().map(|l| l.to_string()).collect())
+}
+
+/// Packages have been installed or removed by other means
+pub(crate) fn installed_changed() {
+    INSTALLED.invalidate();
+}
+
+/// Run pacman with the output shown
+fn run_pacman(args: &[&str], packages: &[&str]) -> io::Result<()> {
+    let s = process::Command::new("pacman")
+        .args(args)
+        .args(packages)
+        .status()?;
+    INSTALLED.invalidate();
+    if !s.success() {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("pacman {} {:?} failed", args.join(" "), packages),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
 /// packages are installed by pacman
 #[derive(Clone)]
 pub struct PacmanInstalled {
     pub packages: Vec<&'static str>,
+    version: Option<VersionReq>,
+    held: bool,
+    /// The configuration used by pacman and holding `IgnorePkg`
+    conf: UserPathBuf,
 }
 
 /// packages are not installed by pacman
@@ -17,6 +95,72 @@ pub struct PacmanRemoved {
     pub packages: Vec<&'static str>,
 }
 
+impl PacmanInstalled {
+    pub fn new(packages: Vec<&'static str>) -> PacmanInstalled {
+        PacmanInstalled {
+            packages,
+            version: None,
+            held: false,
+            conf: UserPathBuf::from(PACMAN_CONF),
+        }
+    }
+
+    /// Installed versions satisfy a requirement such as `>=1.2`,
+    /// compared like vercmp. pacman installs the version in the
+    /// repositories, so this is checked again after installing.
+    #[allow(dead_code)]
+    pub fn version(mut self, req: &str) -> PacmanInstalled {
+        self.version = Some(VersionReq::parse(req));
+        self
+    }
+
+    /// Packages are held back from upgrades with `IgnorePkg`
+    #[allow(dead_code)]
+    pub fn held(mut self) -> PacmanInstalled {
+        self.held = true;
+        self
+    }
+
+    /// Use this configuration file instead of /etc/pacman.conf,
+    /// like `pacman --config`, such as the one given to `conf_at`
+    #[allow(dead_code)]
+    pub fn conf_at<P: Into<UserPathBuf>>(mut self, path: P) -> PacmanInstalled {
+        self.conf = path.into();
+        self
+    }
+
+    /// Packages which are not installed or don't have a required
+    /// version, with their installed versions
+    fn unsatisfied(&self) -> io::Result<Vec<(&'static str, Option<String>)>> {
+        INSTALLED.with(query_installed, |installed| {
+            self.packages
+                .iter()
+                .filter_map(|&p| match installed.get(p) {
+                    None => Some((p, None)),
+                    Some(v) => match &self.version {
+                        Some(req) if !req.matches(v, pacman_cmp) => Some((p, Some(v.clone()))),
+                        _ => None,
+                    },
+                })
+                .collect()
+        })
+    }
+
+    /// Packages which should be held but aren't
+    fn not_held(&self) -> io::Result<Vec<&'static str>> {
+        if !self.held {
+            return Ok(vec![]);
+        }
+        let ignored = ignored_packages(&self.conf)?;
+        Ok(self
+            .packages
+            .iter()
+            .filter(|&&p| !ignored.iter().any(|i| i == p))
+            .cloned()
+            .collect())
+    }
+}
+
 impl fmt::Display for PacmanInstalled {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.packages.len() == 1 {
@@ -24,41 +168,427 @@ impl fmt::Display for PacmanInstalled {
         } else {
             write!(f, "packages {:?} are installed by pacman", self.packages)?;
         }
+        if let Some(req) = &self.version {
+            write!(f, " with version {}", req)?;
+        }
+        if self.held {
+            write!(f, " and held")?;
+        }
 
         Ok(())
     }
 }
 
 impl Property<os::ArchLinux> for PacmanInstalled {
-    fn check(&self) -> PrResult<bool> {
-        for package in &self.packages {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let unsatisfied = self.unsatisfied()?;
+        for (package, version) in &unsatisfied {
+            if let (Some(version), Some(req)) = (version, &self.version) {
+                println!("Package {} is version {}, not {}", package, version, req);
+            }
+        }
+        let not_held = self.not_held()?;
+        if !not_held.is_empty() {
+            println!("Packages not held: {:?}", not_held);
+        }
+        Ok(unsatisfied.is_empty() && not_held.is_empty())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let unsatisfied = self.unsatisfied()?;
+        if !unsatisfied.is_empty() {
             let s = process::Command::new("pacman")
-                // This option retrieves a list of the files installed by a package
-                .arg("-Ql")
-                .arg(package)
+                .arg("--config")
+                .arg(self.conf.expand_user()?)
+                .arg("-S")
+                .arg("--needed")
+                .arg("--noconfirm")
+                .args(&self.packages)
                 .status()?;
+            INSTALLED.invalidate();
             if !s.success() {
-                return Ok(false);
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Packages {:?} not installed successfully", self.packages),
+                ));
+            }
+            let unsatisfied = self.unsatisfied()?;
+            if !unsatisfied.is_empty() {
+                let packages: Vec<&str> = unsatisfied.iter().map(|(p, _)| *p).collect();
+                let msg = match &self.version {
+                    Some(req) => {
+                        format!("Repositories don't have version {} of {:?}", req, packages)
+                    }
+                    None => format!("Packages {:?} not installed successfully", packages),
+                };
+                return Err(io::Error::new(io::ErrorKind::NotFound, msg));
             }
         }
-
-        Ok(true)
+        let not_held = self.not_held()?;
+        if !not_held.is_empty() {
+            ignore_packages(&self.conf, &not_held)?;
+        }
+        Ok(())
     }
+}
+
+/// All packages are upgraded with `pacman -Syu`. Upgrades are found
+/// with `checkupdates` from pacman-contrib, or else the same way it
+/// does, so checking never refreshes the real sync databases.
+#[derive(Clone)]
+pub struct PacmanUpgraded;
 
-    fn apply(&self) -> PrResult<()> {
+/// Database directory of pacman, holding the local and sync databases
+const DB_PATH: &str = "/var/lib/pacman";
+
+/// Packages with upgrades in a temporary copy of the databases, synced
+/// alongside the real local database. Refreshing the real sync
+/// databases without upgrading would leave a partial upgrade.
+fn synced_copy_outdated() -> io::Result<Vec<String>> {
+    let tmp = env::temp_dir().join(format!("rotor-pacman-db-{}", process::id()));
+    let result = (|| {
+        fs::create_dir_all(tmp.join("sync"))?;
+        symlink(Path::new(DB_PATH).join("local"), tmp.join("local"))?;
+        // Databases already downloaded only need their differences
+        if let Ok(entries) = fs::read_dir(Path::new(DB_PATH).join("sync")) {
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    fs::copy(entry.path(), tmp.join("sync").join(entry.file_name()))?;
+                }
+            }
+        }
+        let db_path = tmp.to_string_lossy();
         let s = process::Command::new("pacman")
-            .arg("-S")
-            .arg("--needed")
-            .arg("--noconfirm")
-            .args(&self.packages)
+            .args(["-Sy", "--dbpath", &db_path, "--logfile", "/dev/null"])
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
             .status()?;
         if !s.success() {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Packages {:?} not installed successfully", self.packages),
-            ))
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Syncing a copy of the pacman databases failed",
+            ));
+        }
+        query_names(&["-Qqu", "--dbpath", &db_path])
+    })();
+    let _ = fs::remove_dir_all(&tmp);
+    result
+}
+
+/// Packages with upgrades in freshly synced databases
+fn outdated_packages(facts: &Facts) -> io::Result<Vec<String>> {
+    if !facts.has_command("checkupdates") {
+        return synced_copy_outdated();
+    }
+    let out = process::Command::new("checkupdates")
+        .arg("--nocolor")
+        .stdin(process::Stdio::null())
+        .output()?;
+    // checkupdates exits with 2 when there are no updates
+    if !out.status.success() && out.status.code() != Some(2) {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "checkupdates failed: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .map(|p| p.to_string())
+        .collect())
+}
+
+impl fmt::Display for PacmanUpgraded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "packages are upgraded by pacman")
+    }
+}
+
+impl Property<os::ArchLinux> for PacmanUpgraded {
+    fn check(&self, facts: &Facts) -> PrResult<bool> {
+        let outdated = outdated_packages(facts)?;
+        if !outdated.is_empty() {
+            println!("Packages to upgrade: {:?}", outdated);
+        }
+        Ok(outdated.is_empty())
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        run_pacman(&["-Syu", "--noconfirm"], &[])
+    }
+}
+
+/// Exactly the given packages are explicitly installed. Other
+/// explicitly installed packages and orphaned dependencies are
+/// reported, and removed only if requested.
+#[derive(Clone)]
+pub struct PacmanExclusive {
+    pub packages: Vec<&'static str>,
+    remove: bool,
+}
+
+/// Differences between the declared and installed packages
+struct PacmanReconciliation {
+    /// Declared but not installed
+    missing: Vec<&'static str>,
+    /// Declared but only installed as dependencies
+    dependencies: Vec<String>,
+    /// Explicitly installed but not declared
+    extra: Vec<String>,
+    /// Installed but not needed by any declared package, including
+    /// the extra packages. This is what removing takes away.
+    unneeded: Vec<String>,
+}
+
+/// How an installed package was installed, from `pacman -Qi`
+#[derive(Debug, PartialEq)]
+struct Installation {
+    explicit: bool,
+    /// Installed packages depending on this one
+    required_by: Vec<String>,
+}
+
+/// Install reasons and reverse dependencies of all installed packages
+fn query_installations() -> io::Result<HashMap<String, Installation>> {
+    let out = query_output(
+        process::Command::new("pacman")
+            .arg("-Qi")
+            .env("LC_ALL", "C"),
+    )?;
+    Ok(parse_installations(&out))
+}
+
+fn parse_installations(out: &str) -> HashMap<String, Installation> {
+    let mut installations = HashMap::new();
+    for block in out.split("\n\n") {
+        let mut name = None;
+        let mut explicit = false;
+        let mut required_by = vec![];
+        for line in block.lines() {
+            let mut parts = line.splitn(2, ':');
+            let (field, value) = match (parts.next(), parts.next()) {
+                (Some(field), Some(value)) => (field.trim(), value.trim()),
+                _ => continue,
+            };
+            match field {
+                "Name" => name = Some(value.to_string()),
+                "Install Reason" => explicit = value == "Explicitly installed",
+                "Required By" if value != "None" => {
+                    required_by = value.split_whitespace().map(|p| p.to_string()).collect()
+                }
+                _ => (),
+            }
+        }
+        if let Some(name) = name {
+            let installation = Installation {
+                explicit,
+                required_by,
+            };
+            installations.insert(name, installation);
+        }
+    }
+    installations
+}
+
+/// Installed packages which are neither declared nor, directly or
+/// indirectly, a dependency of a declared package
+fn unneeded(declared: &[&str], installations: &HashMap<String, Installation>) -> Vec<String> {
+    let mut dependencies: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (name, installation) in installations {
+        for dependent in &installation.required_by {
+            dependencies.entry(dependent).or_default().push(name);
+        }
+    }
+    let mut needed: HashSet<&str> = HashSet::new();
+    let mut pending: Vec<&str> = declared
+        .iter()
+        .filter(|&&p| installations.contains_key(p))
+        .cloned()
+        .collect();
+    while let Some(package) = pending.pop() {
+        if needed.insert(package) {
+            pending.extend(dependencies.get(package).into_iter().flatten());
+        }
+    }
+    let mut unneeded: Vec<String> = installations
+        .keys()
+        .filter(|p| !needed.contains(p.as_str()))
+        .cloned()
+        .collect();
+    unneeded.sort();
+    unneeded
+}
+
+impl PacmanExclusive {
+    pub fn new(packages: Vec<&'static str>) -> PacmanExclusive {
+        PacmanExclusive {
+            packages,
+            remove: false,
+        }
+    }
+
+    /// Remove packages which are not declared and not needed by
+    /// declared packages, along with orphaned dependencies
+    #[allow(dead_code)]
+    pub fn removing(mut self) -> PacmanExclusive {
+        self.remove = true;
+        self
+    }
+
+    fn reconcile(&self) -> io::Result<PacmanReconciliation> {
+        let missing = INSTALLED.missing(&self.packages, query_installed)?;
+        let installations = query_installations()?;
+        let dependencies = self
+            .packages
+            .iter()
+            .filter(|&&p| installations.get(p).is_some_and(|i| !i.explicit))
+            .map(|p| p.to_string())
+            .collect();
+        let mut extra: Vec<String> = installations
+            .iter()
+            .filter(|(p, i)| i.explicit && !self.packages.contains(&p.as_str()))
+            .map(|(p, _)| p.clone())
+            .collect();
+        extra.sort();
+        let unneeded = unneeded(&self.packages, &installations);
+        Ok(PacmanReconciliation {
+            missing,
+            dependencies,
+            extra,
+            unneeded,
+        })
+    }
+}
+
+impl fmt::Display for PacmanExclusive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "only {} declared packages are explicitly installed by pacman",
+            self.packages.len()
+        )?;
+        if self.remove {
+            write!(f, ", removing others")?;
+        }
+        Ok(())
+    }
+}
+
+impl Property<os::ArchLinux> for PacmanExclusive {
+    fn check(&self, _: &Facts) -> PrResult<bool> {
+        let r = self.reconcile()?;
+        if !r.extra.is_empty() {
+            println!(
+                "Packages explicitly installed but not declared: {:?}",
+                r.extra
+            );
+        }
+        if self.remove && !r.unneeded.is_empty() {
+            println!("Packages to remove: {:?}", r.unneeded);
+        } else {
+            let orphans: Vec<&String> =
+                r.unneeded.iter().filter(|p| !r.extra.contains(p)).collect();
+            if !orphans.is_empty() {
+                println!("Orphaned packages: {:?}", orphans);
+            }
+        }
+        let declared_ok = r.missing.is_empty() && r.dependencies.is_empty();
+        if self.remove {
+            Ok(declared_ok && r.unneeded.is_empty())
         } else {
-            Ok(())
+            Ok(declared_ok)
+        }
+    }
+
+    fn apply(&self, _: &Facts) -> PrResult<()> {
+        let r = self.reconcile()?;
+        let mut unneeded = r.unneeded;
+        if !r.missing.is_empty() {
+            run_pacman(&["-S", "--needed", "--noconfirm"], &r.missing)?;
+            // Newly installed packages may depend on ones which weren't
+            // needed before; only what is still unneeded is removed
+            let now = unneeded_packages(&self.packages)?;
+            unneeded.retain(|p| now.contains(p));
         }
+        if !r.dependencies.is_empty() {
+            let deps: Vec<&str> = r.dependencies.iter().map(|s| s.as_str()).collect();
+            run_pacman(&["-D", "--asexplicit"], &deps)?;
+        }
+        if !self.remove || unneeded.is_empty() {
+            return Ok(());
+        }
+        // Nothing outside the set depends on it, so it is removed as
+        // is, without cascading to packages which weren't reported
+        println!("Removing packages: {}", unneeded.join(" "));
+        let unneeded: Vec<&str> = unneeded.iter().map(|s| s.as_str()).collect();
+        run_pacman(&["-Rn", "--noconfirm"], &unneeded)
+    }
+}
+
+/// Installed packages not needed by the given ones
+fn unneeded_packages(declared: &[&str]) -> io::Result<Vec<String>> {
+    Ok(unneeded(declared, &query_installations()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+Name            : base
+Version         : 3-2
+Depends On      : filesystem  glibc
+Required By     : None
+Install Reason  : Explicitly installed
+
+Name            : glibc
+Version         : 2.39-1
+Required By     : base  vim
+Install Reason  : Installed as a dependency for another package
+
+Name            : vim
+Version         : 9.1-1
+Required By     : None
+Install Reason  : Explicitly installed
+
+Name            : gpm
+Version         : 1.20-1
+Required By     : vim
+Install Reason  : Installed as a dependency for another package
+
+Name            : filesystem
+Version         : 2024-1
+Required By     : base
+Install Reason  : Installed as a dependency for another package
+
+Name            : python
+Version         : 3.12-1
+Required By     : None
+Install Reason  : Installed as a dependency for another package
+";
+
+    #[test]
+    fn test_unneeded() {
+        let installations = parse_installations(SAMPLE);
+        assert_eq!(installations.len(), 6);
+        assert_eq!(
+            installations["glibc"],
+            Installation {
+                explicit: false,
+                required_by: vec!["base".to_string(), "vim".to_string()],
+            }
+        );
+        assert!(installations["vim"].explicit);
+        // glibc is kept for base even though vim is removed, and the
+        // dependency of vim goes with it
+        assert_eq!(
+            unneeded(&["base"], &installations),
+            vec!["gpm", "python", "vim"]
+        );
+        assert_eq!(unneeded(&["base", "vim"], &installations), vec!["python"]);
     }
 }
@@ -1,6 +1,11 @@
+mod conf;
 mod internal;
 
-use self::internal::{PacmanInstalled, PacmanRemoved};
+use self::conf::{PacmanConfFile, PACMAN_CONF};
+pub(crate) use self::internal::{installed_changed, query_names};
+use self::internal::{PacmanExclusive, PacmanInstalled, PacmanRemoved, PacmanUpgraded};
+use crate::util::UserPathBuf;
+use std::sync::Arc;
 
 /// package is installed by pacman
 pub fn installed(package: &'static str) -> PacmanInstalled {
@@ -9,8 +14,7 @@ pub fn installed(package: &'static str) -> PacmanInstalled {
 
 /// packages are installed by pacman
 pub fn installed_all(packages: &[&'static str]) -> PacmanInstalled {
-    let packages = packages.to_vec();
-    PacmanInstalled { packages }
+    PacmanInstalled::new(packages.to_vec())
 }
 
 /// Package is not installed by pacman.
@@ -23,3 +27,30 @@ pub fn removed_all(packages: &[&'static str]) -> PacmanRemoved {
     let packages = packages.to_vec();
     PacmanRemoved { packages }
 }
+
+/// All packages are upgraded. Checking finds upgrades without
+/// refreshing the sync databases.
+#[allow(dead_code)]
+pub fn upgraded() -> PacmanUpgraded {
+    PacmanUpgraded
+}
+
+/// Packages are the only ones explicitly installed by pacman.
+/// Others are reported, or removed with `removing()`.
+#[allow(dead_code)]
+pub fn exclusive(packages: &[&'static str]) -> PacmanExclusive {
+    PacmanExclusive::new(packages.to_vec())
+}
+
+/// Options and repositories in /etc/pacman.conf
+#[allow(dead_code)]
+pub fn conf() -> PacmanConfFile {
+    conf_at(PACMAN_CONF)
+}
+
+/// Options and repositories in a pacman configuration file
+#[allow(dead_code)]
+pub fn conf_at<P: Into<UserPathBuf>>(path: P) -> PacmanConfFile {
+    let path = Arc::new(path.into());
+    PacmanConfFile { path }
+}
@@ -0,0 +1,319 @@
+//! Version requirements of packages, compared following the rules of
+//! pacman (vercmp) and dpkg
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A version with a comparison operator, such as `>=1.2`.
+/// Without an operator, the version must be equal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionReq {
+    op: Op,
+    version: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Less,
+    LessEq,
+    Eq,
+    GreaterEq,
+    Greater,
+}
+
+impl VersionReq {
+    pub fn parse(req: &str) -> VersionReq {
+        let req = req.trim();
+        // Longer operators first
+        let ops = [
+            (">=", Op::GreaterEq),
+            ("<=", Op::LessEq),
+            ("==", Op::Eq),
+            (">", Op::Greater),
+            ("<", Op::Less),
+            ("=", Op::Eq),
+        ];
+        for &(prefix, op) in &ops {
+            if let Some(version) = req.strip_prefix(prefix) {
+                let version = version.trim().to_string();
+                return VersionReq { op, version };
+            }
+        }
+        VersionReq {
+            op: Op::Eq,
+            version: req.to_string(),
+        }
+    }
+
+    pub fn op(&self) -> Op {
+        self.op
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Whether the installed version satisfies the requirement,
+    /// using a comparison function of the package manager
+    pub fn matches<F>(&self, installed: &str, compare: F) -> bool
+    where
+        F: Fn(&str, &str) -> Ordering,
+    {
+        let ord = compare(installed, &self.version);
+        match self.op {
+            Op::Less => ord == Ordering::Less,
+            Op::LessEq => ord != Ordering::Greater,
+            Op::Eq => ord == Ordering::Equal,
+            Op::GreaterEq => ord != Ordering::Less,
+            Op::Greater => ord == Ordering::Greater,
+        }
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op {
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Eq => "=",
+            Op::GreaterEq => ">=",
+            Op::Greater => ">",
+        };
+        write!(f, "{}{}", op, self.version)
+    }
+}
+
+/// Split `epoch:version-release`; the epoch defaults to 0 and the
+/// release is after the last `-`
+fn split_evr(v: &str) -> (&str, &str, Option<&str>) {
+    let (epoch, rest) = match v.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) if v[i..].starts_with(':') => (&v[..i], &v[i + 1..]),
+        _ => ("0", v),
+    };
+    let epoch = if epoch.is_empty() { "0" } else { epoch };
+    match rest.rfind('-') {
+        Some(i) => (epoch, &rest[..i], Some(&rest[i + 1..])),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Compare versions like pacman's vercmp
+pub fn pacman_cmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (epoch_a, ver_a, rel_a) = split_evr(a);
+    let (epoch_b, ver_b, rel_b) = split_evr(b);
+    rpmvercmp(epoch_a, epoch_b)
+        .then_with(|| rpmvercmp(ver_a, ver_b))
+        .then_with(|| match (rel_a, rel_b) {
+            (Some(rel_a), Some(rel_b)) => rpmvercmp(rel_a, rel_b),
+            // A missing release matches any release
+            _ => Ordering::Equal,
+        })
+}
+
+/// The segment comparison used by pacman, inherited from rpm
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let (mut one, mut two) = (0, 0);
+    while one < a.len() && two < b.len() {
+        let (start_one, start_two) = (one, two);
+        while one < a.len() && !a[one].is_ascii_alphanumeric() {
+            one += 1;
+        }
+        while two < b.len() && !b[two].is_ascii_alphanumeric() {
+            two += 1;
+        }
+        if one == a.len() || two == b.len() {
+            break;
+        }
+        // Differing numbers of separators
+        if one - start_one != two - start_two {
+            return (one - start_one).cmp(&(two - start_two));
+        }
+        let is_num = a[one].is_ascii_digit();
+        let class = |c: &u8| {
+            if is_num {
+                c.is_ascii_digit()
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        };
+        let end_one = one + a[one..].iter().take_while(|c| class(c)).count();
+        let end_two = two + b[two..].iter().take_while(|c| class(c)).count();
+        if end_two == two {
+            // Numbers are newer than letters
+            return if is_num {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let mut seg_one = &a[one..end_one];
+        let mut seg_two = &b[two..end_two];
+        if is_num {
+            while seg_one.first() == Some(&b'0') {
+                seg_one = &seg_one[1..];
+            }
+            while seg_two.first() == Some(&b'0') {
+                seg_two = &seg_two[1..];
+            }
+            if seg_one.len() != seg_two.len() {
+                return seg_one.len().cmp(&seg_two.len());
+            }
+        }
+        match seg_one.cmp(seg_two) {
+            Ordering::Equal => {}
+            ord => return ord,
+        }
+        one = end_one;
+        two = end_two;
+    }
+    let rest_one = &a[one..];
+    let rest_two = &b[two..];
+    if rest_one.is_empty() && rest_two.is_empty() {
+        return Ordering::Equal;
+    }
+    // A remaining alpha segment is older than nothing, anything
+    // else is newer
+    let alpha_one = rest_one.first().is_some_and(|c| c.is_ascii_alphabetic());
+    let alpha_two = rest_two.first().is_some_and(|c| c.is_ascii_alphabetic());
+    if (rest_one.is_empty() && !alpha_two) || alpha_one {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+/// Compare versions like `dpkg --compare-versions`
+pub fn dpkg_cmp(a: &str, b: &str) -> Ordering {
+    let (epoch_a, ver_a, rev_a) = split_evr(a);
+    let (epoch_b, ver_b, rev_b) = split_evr(b);
+    let epoch_a: u64 = epoch_a.parse().unwrap_or(0);
+    let epoch_b: u64 = epoch_b.parse().unwrap_or(0);
+    epoch_a
+        .cmp(&epoch_b)
+        .then_with(|| verrevcmp(ver_a, ver_b))
+        .then_with(|| verrevcmp(rev_a.unwrap_or(""), rev_b.unwrap_or("")))
+}
+
+/// Sort order of a character in the non-digit parts of versions:
+/// `~` is before everything, even the end, and letters are before
+/// other characters
+fn dpkg_order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => i32::from(c),
+        Some(b'~') => -1,
+        Some(c) => i32::from(c) + 256,
+    }
+}
+
+fn verrevcmp(a: &str, b: &str) -> Ordering {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let (mut i, mut j) = (0, 0);
+    let digit = |s: &[u8], k: usize| s.get(k).is_some_and(|c| c.is_ascii_digit());
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !digit(a, i)) || (j < b.len() && !digit(b, j)) {
+            let ac = dpkg_order(a.get(i).cloned());
+            let bc = dpkg_order(b.get(j).cloned());
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while digit(a, i) && digit(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if digit(a, i) {
+            return Ordering::Greater;
+        }
+        if digit(b, j) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering::*;
+
+    #[test]
+    fn test_pacman_cmp() {
+        let cases = [
+            ("1.0", "1.0", Equal),
+            ("1.0", "1.0.1", Less),
+            ("1.0a", "1.0", Less),
+            ("1.0a", "1.0b", Less),
+            ("1.0.a", "1.0.1", Less),
+            ("1.0alpha", "1.0.alpha", Less),
+            ("1.0-1", "1.0-2", Less),
+            ("1.0-1", "1.0", Equal),
+            ("1:1.0", "2.0", Greater),
+            ("1.01", "1.1", Equal),
+            ("1.10", "1.9", Greater),
+            ("1.0_1", "1.0.1", Equal),
+            ("1.0..1", "1.0.1", Greater),
+        ];
+        for &(a, b, ord) in &cases {
+            assert_eq!(pacman_cmp(a, b), ord, "{} {}", a, b);
+            assert_eq!(pacman_cmp(b, a), ord.reverse(), "{} {}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_dpkg_cmp() {
+        let cases = [
+            ("1.2-3", "1.2-3", Equal),
+            ("1.2-3", "1.2-10", Less),
+            ("1.2~rc1", "1.2", Less),
+            ("1.2~rc1", "1.2~~", Greater),
+            ("1.2a", "1.2+", Less),
+            ("1:0.1", "2.0", Greater),
+            ("1.02", "1.2", Equal),
+            ("2.0", "10.0", Less),
+            ("1.2", "1.2-0", Equal),
+        ];
+        for &(a, b, ord) in &cases {
+            assert_eq!(dpkg_cmp(a, b), ord, "{} {}", a, b);
+            assert_eq!(dpkg_cmp(b, a), ord.reverse(), "{} {}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_version_req() {
+        let req = VersionReq::parse(">=1.2");
+        assert_eq!(req.op(), Op::GreaterEq);
+        assert!(req.matches("1.2-1", pacman_cmp));
+        assert!(req.matches("1.10", pacman_cmp));
+        assert!(!req.matches("1.1", pacman_cmp));
+        let req = VersionReq::parse("1.2-3");
+        assert_eq!(req.to_string(), "=1.2-3");
+        assert!(req.matches("1.2-3", dpkg_cmp));
+        assert!(!req.matches("1.2-4", dpkg_cmp));
+    }
+}
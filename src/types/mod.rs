@@ -1 +1,2 @@
 pub mod os;
+mod os_release;
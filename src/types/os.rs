@@ -1,3 +1,5 @@
+pub use super::os_release::OsRelease;
+
 /// Any environment where this program runs
 #[derive(Default)]
 pub struct Any;
@@ -8,10 +10,65 @@ pub struct ArchLinux;
 /// Debian and its derivatives
 #[derive(Default)]
 pub struct DebianLike;
+/// Fedora, RHEL and their derivatives
+#[derive(Default)]
+pub struct RedHatLike;
+/// Alpine Linux, with apk
+#[derive(Default)]
+pub struct Alpine;
+
+pub trait OS: Default {
+    /// Name shown when a host runs another OS
+    const NAME: &'static str;
+
+    /// Whether a host identified by os-release runs this OS
+    fn matches(release: &OsRelease) -> bool;
+}
+
+impl OS for Any {
+    const NAME: &'static str = "any OS";
+
+    fn matches(_: &OsRelease) -> bool {
+        true
+    }
+}
+
+impl OS for Linux {
+    const NAME: &'static str = "Linux";
+
+    fn matches(_: &OsRelease) -> bool {
+        cfg!(target_os = "linux")
+    }
+}
+
+impl OS for ArchLinux {
+    const NAME: &'static str = "Arch Linux";
+
+    fn matches(release: &OsRelease) -> bool {
+        release.is_like(&["arch"])
+    }
+}
+
+impl OS for DebianLike {
+    const NAME: &'static str = "Debian or a derivative";
+
+    fn matches(release: &OsRelease) -> bool {
+        release.is_like(&["debian", "ubuntu"])
+    }
+}
+
+impl OS for RedHatLike {
+    const NAME: &'static str = "Fedora, RHEL or a derivative";
+
+    fn matches(release: &OsRelease) -> bool {
+        release.is_like(&["fedora", "rhel", "centos"])
+    }
+}
 
-pub trait OS: Default {}
+impl OS for Alpine {
+    const NAME: &'static str = "Alpine Linux";
 
-impl OS for Any {}
-impl OS for Linux {}
-impl OS for ArchLinux {}
-impl OS for DebianLike {}
+    fn matches(release: &OsRelease) -> bool {
+        release.is_like(&["alpine"])
+    }
+}
@@ -0,0 +1,137 @@
+//! Identification of the running distribution from os-release
+
+use std::fmt;
+use std::fs;
+use std::io;
+
+const OS_RELEASE: &str = "/etc/os-release";
+const OS_RELEASE_FALLBACK: &str = "/usr/lib/os-release";
+
+/// Fields of os-release identifying the distribution
+#[derive(Clone, Debug, PartialEq)]
+pub struct OsRelease {
+    /// Such as `debian` or `arch`, `linux` if not given
+    pub id: String,
+    /// Distributions this one is derived from, closest first
+    pub id_like: Vec<String>,
+    pub pretty_name: Option<String>,
+}
+
+impl Default for OsRelease {
+    fn default() -> OsRelease {
+        OsRelease {
+            id: "linux".to_string(),
+            id_like: vec![],
+            pretty_name: None,
+        }
+    }
+}
+
+impl OsRelease {
+    /// Read `/etc/os-release`, or `/usr/lib/os-release` if it doesn't
+    /// exist
+    pub fn read() -> io::Result<OsRelease> {
+        let content = match fs::read_to_string(OS_RELEASE) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                fs::read_to_string(OS_RELEASE_FALLBACK)?
+            }
+            content => content?,
+        };
+        Ok(OsRelease::parse(&content))
+    }
+
+    /// Parse shell-style assignments such as `ID_LIKE="rhel fedora"`
+    pub fn parse(content: &str) -> OsRelease {
+        let mut release = OsRelease::default();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(k), Some(v)) => (k.trim(), unquote(v.trim())),
+                _ => continue,
+            };
+            match key {
+                "ID" => release.id = value,
+                "ID_LIKE" => release.id_like = value.split_whitespace().map(String::from).collect(),
+                "PRETTY_NAME" => release.pretty_name = Some(value),
+                _ => {}
+            }
+        }
+        release
+    }
+
+    /// Whether the distribution is or is derived from one of the ids
+    pub fn is_like(&self, ids: &[&str]) -> bool {
+        ids.iter()
+            .any(|&id| self.id == id || self.id_like.iter().any(|l| l == id))
+    }
+}
+
+impl fmt::Display for OsRelease {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.pretty_name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.id),
+        }
+    }
+}
+
+/// Remove quotes and backslash escapes of a value
+fn unquote(value: &str) -> String {
+    let quoted = value.len() >= 2
+        && (value.starts_with('"') && value.ends_with('"')
+            || value.starts_with('\'') && value.ends_with('\''));
+    let value = if quoted {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    let mut unquoted = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::os::{self, OS};
+
+    #[test]
+    fn test_os_release() {
+        let rocky = OsRelease::parse(
+            "NAME=\"Rocky Linux\"\n\
+             ID=\"rocky\"\n\
+             ID_LIKE=\"rhel centos fedora\"\n\
+             # a comment\n\
+             PRETTY_NAME=\"Rocky Linux 9.3 (Blue Onyx)\"\n",
+        );
+        assert_eq!(rocky.id, "rocky");
+        assert_eq!(rocky.id_like, vec!["rhel", "centos", "fedora"]);
+        assert_eq!(rocky.to_string(), "Rocky Linux 9.3 (Blue Onyx)");
+        assert!(os::RedHatLike::matches(&rocky));
+        assert!(!os::DebianLike::matches(&rocky));
+
+        let mint = OsRelease::parse("ID=linuxmint\nID_LIKE='ubuntu debian'\n");
+        assert!(os::DebianLike::matches(&mint));
+        assert!(!os::ArchLinux::matches(&mint));
+
+        let arch = OsRelease::parse("ID=arch\nPRETTY_NAME=\"Arch \\\"Linux\\\"\"\n");
+        assert!(os::ArchLinux::matches(&arch));
+        assert!(os::Linux::matches(&arch));
+        assert_eq!(arch.pretty_name.as_deref(), Some("Arch \"Linux\""));
+
+        let unknown = OsRelease::parse("");
+        assert_eq!(unknown.id, "linux");
+        assert!(os::Any::matches(&unknown));
+        assert!(!os::Alpine::matches(&unknown));
+    }
+}
@@ -1,4 +1,5 @@
 use super::super::host::UserAtHost;
+use std::fmt;
 use std::str::FromStr;
 use structopt::StructOpt;
 
@@ -14,34 +15,47 @@ pub enum RotorSub {
     #[structopt(name = "list")]
     /// List configured users at hosts
     List,
-    /// Apply configurations for username@hostname locally
+    /// Apply configurations for username@hostname, or all users of a
+    /// hostname, locally. By default the current user at this host.
     #[structopt(name = "apply")]
     Apply {
         #[structopt(parse(try_from_str))]
-        user: UserAtHost,
+        target: Option<Target>,
+        /// Apply even if the host runs another OS than configured
+        #[structopt(long = "force")]
+        force: bool,
     },
     /// Apply configurations to remote users or hosts via ssh
     #[structopt(name = "push")]
-    Push { targets: Vec<PushTarget> },
+    Push { targets: Vec<Target> },
 }
 
 #[derive(Debug)]
-pub enum PushTarget {
+pub enum Target {
     /// A single user on a single host
     User(UserAtHost),
     /// All users on a host
     Host(String),
 }
 
-impl FromStr for PushTarget {
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Target::User(u) => write!(f, "{}", u),
+            Target::Host(h) => write!(f, "{}", h),
+        }
+    }
+}
+
+impl FromStr for Target {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if !s.contains('@') {
-            Ok(PushTarget::Host(s.to_string()))
+            Ok(Target::Host(s.to_string()))
         } else {
             let u = FromStr::from_str(s)?;
-            Ok(PushTarget::User(u))
+            Ok(Target::User(u))
         }
     }
 }
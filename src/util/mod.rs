@@ -1,10 +1,32 @@
-use dirs::home_dir;
+use std::cell::RefCell;
 use std::fmt;
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
 
 pub mod cmd;
+pub mod passwd;
+pub mod privileges;
+
+thread_local! {
+    /// Home directory of the user being configured, when it's not the
+    /// user running rotor. Users are configured one at a time by the
+    /// thread running them.
+    static TARGET_HOME: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Resolve relative paths in the home directory of the user being
+/// configured, or of the user running rotor if `None`
+pub fn set_target_home(home: Option<PathBuf>) {
+    TARGET_HOME.with(|target| *target.borrow_mut() = home);
+}
+
+/// Home directory of the user being configured
+pub fn home_dir() -> Option<PathBuf> {
+    TARGET_HOME
+        .with(|target| target.borrow().clone())
+        .or_else(dirs::home_dir)
+}
 
 /// Relative paths are considered relative to user's home directory
 #[derive(Clone)]
@@ -58,8 +80,8 @@ impl UserPathBuf {
 
     pub fn path(&self) -> &Path {
         match self {
-            UserPathBuf::Home(p) => &p,
-            UserPathBuf::Absolute(p) => &p,
+            UserPathBuf::Home(p) => p,
+            UserPathBuf::Absolute(p) => p,
         }
     }
 
@@ -74,3 +96,89 @@ impl UserPathBuf {
         }
     }
 }
+
+/// Names which may have been meant instead of the given one: the
+/// same ignoring case, a prefix such as a short hostname, or a few
+/// edits away, allowing more edits for longer names
+pub fn near_matches<'a, I>(name: &str, candidates: I) -> Vec<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    /// Shorter prefixes match too much
+    const MIN_PREFIX: usize = 3;
+    let name = name.to_lowercase();
+    let max_edits = (name.chars().count() / 4).min(2);
+    let is_prefix =
+        |short: &str, long: &str| short.chars().count() >= MIN_PREFIX && long.starts_with(short);
+    candidates
+        .into_iter()
+        .filter(|c| !c.is_empty() && !name.is_empty())
+        .filter(|c| {
+            let c = c.to_lowercase();
+            c == name
+                || is_prefix(&name, &c)
+                || is_prefix(&c, &name)
+                || edit_distance(&c, &name) <= max_edits
+        })
+        .collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let subst = prev[j] + if ca == *cb { 0 } else { 1 };
+            cur.push(subst.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_home() {
+        let path = UserPathBuf::from(".bashrc");
+        set_target_home(Some(PathBuf::from("/home/flandre")));
+        assert_eq!(
+            path.expand_user().unwrap(),
+            PathBuf::from("/home/flandre/.bashrc")
+        );
+        assert_eq!(home_dir(), Some(PathBuf::from("/home/flandre")));
+        set_target_home(None);
+        assert_eq!(home_dir(), dirs::home_dir());
+    }
+
+    #[test]
+    fn test_near_matches() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        let hosts = ["teufelsschloss", "localhost", "192.168.1.1", "fedora"];
+        assert_eq!(
+            near_matches("teufelsschloss.lan", hosts.iter().cloned()),
+            vec!["teufelsschloss"]
+        );
+        assert_eq!(
+            near_matches("Fedora", hosts.iter().cloned()),
+            vec!["fedora"]
+        );
+        assert_eq!(
+            near_matches("192.168.1.2", hosts.iter().cloned()),
+            vec!["192.168.1.1"]
+        );
+        assert!(near_matches("archlinux", hosts.iter().cloned()).is_empty());
+        assert!(near_matches("", hosts.iter().cloned()).is_empty());
+        assert!(near_matches("f", hosts.iter().cloned()).is_empty());
+        assert!(near_matches("abc", ["", "abd", "xyz"].iter().cloned()).is_empty());
+        assert_eq!(
+            near_matches("fedorq", hosts.iter().cloned()),
+            vec!["fedora"]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Accounts of the passwd database, including those from NSS
+
+use std::ffi::{CStr, CString, OsStr};
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::ptr;
+
+/// An entry of the passwd database
+#[derive(Clone, Debug, PartialEq)]
+pub struct Passwd {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+    pub shell: String,
+}
+
+/// Look up an entry with `getpwnam_r` or `getpwuid_r`, growing the
+/// buffer for strings while it is too small
+fn lookup<F>(get: F) -> io::Result<Option<Passwd>>
+where
+    F: Fn(
+        *mut libc::passwd,
+        *mut libc::c_char,
+        libc::size_t,
+        *mut *mut libc::passwd,
+    ) -> libc::c_int,
+{
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut entry: libc::passwd = unsafe { mem::zeroed() };
+        let mut result = ptr::null_mut();
+        let ret = get(&mut entry, buf.as_mut_ptr(), buf.len(), &mut result);
+        if ret == libc::ERANGE {
+            let len = buf.len() * 2;
+            buf.resize(len, 0);
+            continue;
+        }
+        if ret != 0 {
+            return Err(io::Error::from_raw_os_error(ret));
+        }
+        if result.is_null() {
+            return Ok(None);
+        }
+        let string = |s: *const libc::c_char| unsafe { CStr::from_ptr(s) };
+        return Ok(Some(Passwd {
+            name: string(entry.pw_name).to_string_lossy().into_owned(),
+            uid: entry.pw_uid,
+            gid: entry.pw_gid,
+            home: PathBuf::from(OsStr::from_bytes(string(entry.pw_dir).to_bytes())),
+            shell: string(entry.pw_shell).to_string_lossy().into_owned(),
+        }));
+    }
+}
+
+impl Passwd {
+    /// Parse a line such as `root:x:0:0:root:/root:/bin/bash`
+    #[cfg(test)]
+    fn parse_line(line: &str) -> Option<Passwd> {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() != 7 {
+            return None;
+        }
+        Some(Passwd {
+            name: fields[0].to_string(),
+            uid: fields[2].parse().ok()?,
+            gid: fields[3].parse().ok()?,
+            home: PathBuf::from(fields[5]),
+            shell: fields[6].to_string(),
+        })
+    }
+
+    #[cfg(test)]
+    fn parse(content: &str) -> Vec<Passwd> {
+        content.lines().filter_map(Passwd::parse_line).collect()
+    }
+
+    /// The account of a user name, if there is one
+    pub fn by_name(name: &str) -> io::Result<Option<Passwd>> {
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(_) => return Ok(None),
+        };
+        lookup(|entry, buf, len, result| unsafe {
+            libc::getpwnam_r(name.as_ptr(), entry, buf, len, result)
+        })
+    }
+
+    /// The account of a uid, if there is one
+    pub fn by_uid(uid: u32) -> io::Result<Option<Passwd>> {
+        lookup(|entry, buf, len, result| unsafe { libc::getpwuid_r(uid, entry, buf, len, result) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_passwd() {
+        let entries = Passwd::parse(
+            "root:x:0:0:root:/root:/bin/bash\n\
+             # not an entry\n\
+             flandre:x:1000:1000:Flandre,,,:/home/flandre:/usr/bin/fish\n",
+        );
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "root");
+        assert_eq!(entries[1].uid, 1000);
+        assert_eq!(entries[1].home, PathBuf::from("/home/flandre"));
+        assert_eq!(entries[1].shell, "/usr/bin/fish");
+        assert!(Passwd::parse_line("flandre:x:many:1000::/home/flandre:/bin/sh").is_none());
+    }
+
+    #[test]
+    fn test_lookup() {
+        // Entries of /etc/passwd are found through NSS too
+        let content = fs::read_to_string("/etc/passwd").unwrap();
+        let root = Passwd::parse(&content)
+            .into_iter()
+            .find(|p| p.uid == 0)
+            .unwrap();
+        assert_eq!(Passwd::by_uid(0).unwrap(), Some(root.clone()));
+        assert_eq!(Passwd::by_name(&root.name).unwrap(), Some(root));
+        assert_eq!(Passwd::by_name("rotor-no-such-user").unwrap(), None);
+        assert_eq!(Passwd::by_name("no\0user").unwrap(), None);
+    }
+}
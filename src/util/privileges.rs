@@ -0,0 +1,159 @@
+//! Acting as another user when running as root, so files are created
+//! with their ownership and permissions. This is done in a child
+//! process which drops root for good, along with the programs it runs.
+
+use super::passwd::Passwd;
+use std::env;
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::panic;
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Become the user of an account, with all of its groups
+fn switch_to(account: &Passwd) -> io::Result<()> {
+    let name = CString::new(account.name.as_str())?;
+    // Groups can only be changed while still privileged
+    check(unsafe { libc::initgroups(name.as_ptr(), account.gid) })?;
+    check(unsafe { libc::setgid(account.gid) })?;
+    check(unsafe { libc::setuid(account.uid) })?;
+    // Programs we run look for configuration in the user's home
+    env::set_var("HOME", &account.home);
+    env::set_var("USER", &account.name);
+    env::set_var("LOGNAME", &account.name);
+    env::remove_var("XDG_CONFIG_HOME");
+    // The current directory may not be accessible to the user
+    let _ = env::set_current_dir(&account.home);
+    Ok(())
+}
+
+fn encode(result: &io::Result<bool>) -> String {
+    match result {
+        Ok(done) => done.to_string(),
+        Err(e) => format!("error {}", e),
+    }
+}
+
+fn decode(account: &Passwd, out: &str) -> io::Result<bool> {
+    match out {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => {
+            let msg = match out.strip_prefix("error ") {
+                Some(msg) => msg.to_string(),
+                None => format!("Running as {} ended without a result", account.name),
+            };
+            Err(io::Error::new(io::ErrorKind::Other, msg))
+        }
+    }
+}
+
+fn wait(pid: libc::pid_t) -> io::Result<()> {
+    let mut status = 0;
+    while unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Run in a child process with the user and groups of an account,
+/// and its home directory in `HOME`. The result is passed back to
+/// this process, which keeps its privileges.
+pub fn as_user<F>(account: &Passwd, f: F) -> io::Result<bool>
+where
+    F: FnOnce() -> io::Result<bool>,
+{
+    // Buffered output would be written by both processes
+    io::stdout().flush()?;
+    io::stderr().flush()?;
+    let mut fds = [0; 2];
+    check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    let (reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if pid == 0 {
+        drop(reader);
+        let result = switch_to(account).and_then(|()| {
+            panic::catch_unwind(panic::AssertUnwindSafe(f)).unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Panicked while running as {}", account.name),
+                ))
+            })
+        });
+        let mut writer = writer;
+        let _ = writer.write_all(encode(&result).as_bytes());
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        unsafe { libc::_exit(0) }
+    }
+    drop(writer);
+    let mut out = String::new();
+    let mut reader = reader;
+    let read = reader.read_to_string(&mut out);
+    wait(pid)?;
+    read?;
+    decode(account, &out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// An account which doesn't need to exist, with a home only
+    /// created when needed
+    fn test_account(home: PathBuf) -> Passwd {
+        Passwd {
+            name: "rotor-test".to_string(),
+            uid: 65534,
+            gid: 65534,
+            home,
+            shell: "/bin/sh".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_as_user() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let home = temp_dir().join("rotor-test-home");
+        let account = test_account(home.clone());
+        let switched = as_user(&account, || {
+            let ids = unsafe { (libc::getuid(), libc::geteuid(), libc::getgid()) };
+            // Programs started by a shell keep the user too
+            let out = process::Command::new("sh")
+                .args(["-c", "id -u; id -G"])
+                .output()?;
+            let out = String::from_utf8_lossy(&out.stdout).into_owned();
+            Ok(ids == (65534, 65534, 65534)
+                && out == "65534\n65534\n"
+                && env::var_os("HOME") == Some(home.into_os_string()))
+        });
+        assert!(switched.unwrap());
+        assert_eq!(unsafe { libc::geteuid() }, 0);
+
+        let failed = as_user(&account, || {
+            Err(io::Error::new(io::ErrorKind::Other, "nope"))
+        });
+        assert_eq!(failed.unwrap_err().to_string(), "nope");
+        assert!(!as_user(&account, || Ok(false)).unwrap());
+    }
+}